@@ -0,0 +1,349 @@
+use std::env;
+use std::io::{self, BufRead};
+use std::time::{Duration, Instant};
+
+mod dense;
+mod external;
+mod fuzzy;
+mod input;
+mod lists;
+mod live;
+#[cfg(test)]
+#[path = "../common/rng.rs"]
+mod rng;
+mod sharded;
+mod stats;
+
+use input::{Column, InputFormat, InputOptions};
+use live::LiveLocationLists;
+
+const USAGE: &str = "<distance|similarity|fuzzy|stats|live|external> <filename> \
+[--format whitespace|csv|tsv] [--delimiter C] [--header] [--columns LEFT,RIGHT] \
+[--tolerance K] [--weighted] [--mem-cap BYTES] [--threads N] [--profile]
+  live then reads updates from stdin, one per line: +L ID, -L ID, +R ID or -R ID";
+
+struct Options {
+    filename: String,
+    input: InputOptions,
+    memory_cap: usize,
+    tolerance: u32,
+    weighted: bool,
+    threads: usize,
+    profile: bool,
+}
+
+// Which implementation ran and how long each phase took, for --profile
+struct Profile {
+    path: String,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    fn time<T, F: FnOnce() -> T>(&mut self, phase: &'static str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((phase, start.elapsed()));
+        result
+    }
+
+    fn print(&self) {
+        println!("Profile: {} path", self.path);
+        for (phase, elapsed) in &self.phases {
+            println!("  {:<12} {:?}", phase, elapsed);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    // Get command line arguments
+    let args: Vec<String> = env::args().collect();
+
+    // Check if a command and filename were provided
+    if args.len() < 3 {
+        eprintln!("Usage: {} {}", args[0], USAGE);
+        std::process::exit(1);
+    }
+
+    let command = &args[1];
+    let options = match parse_options(&args[2..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: {} {}", args[0], USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if command == "external" {
+        let totals = external::process(&options.filename, &options.input, options.memory_cap)?;
+        println!("Rows: {} in {} sorted runs ({} merge passes)", totals.rows, totals.runs, totals.merge_passes);
+        println!("Total distance between paired numbers: {}", totals.distance);
+        println!("Similarity score: {}", totals.similarity);
+        return Ok(());
+    }
+
+    let mut profile = Profile { path: String::from("general"), phases: Vec::new() };
+    let (mut vec_a, mut vec_b) = profile.time("parse", || lists::load_lists(&options.filename, &options.input))?;
+    let strategy = if command == "live" {
+        // Inserts one ID at a time, nothing gets sorted or counted up front
+        profile.path = String::from("live");
+        Strategy::General
+    } else if options.threads > 1 {
+        profile.path = format!("sharded ({} threads)", options.threads);
+        Strategy::Sharded(options.threads)
+    } else {
+        match profile.time("range scan", || dense::dense_range(&vec_a, &vec_b)) {
+            Some(range) => {
+                profile.path = format!("dense (IDs {}..={}, span {})", range.min, range.max, range.span());
+                Strategy::Dense(range)
+            }
+            None => Strategy::General,
+        }
+    };
+
+    match command.as_str() {
+        "distance" => {
+            profile.time("sort", || sort_lists(&mut vec_a, &mut vec_b, strategy));
+            let distance = profile.time("distance", || lists::total_distance(&vec_a, &vec_b));
+            println!("Total distance between paired numbers: {}", distance);
+        }
+        "similarity" => {
+            let similarity = profile.time("similarity", || similarity(&vec_a, &vec_b, strategy));
+            println!("Similarity score: {}", similarity);
+        }
+        "fuzzy" => {
+            profile.time("sort", || sort_lists(&mut vec_a, &mut vec_b, strategy));
+            if options.weighted {
+                let similarity = profile.time("similarity", || {
+                    fuzzy::weighted_similarity_score(&vec_a, &vec_b, options.tolerance)
+                });
+                println!("Weighted similarity score (tolerance {}): {:.3}", options.tolerance, similarity);
+            } else {
+                let similarity = profile.time("similarity", || {
+                    fuzzy::similarity_score(&vec_a, &vec_b, options.tolerance)
+                });
+                println!("Similarity score (tolerance {}): {}", options.tolerance, similarity);
+            }
+        }
+        "stats" => {
+            profile.time("sort", || sort_lists(&mut vec_a, &mut vec_b, strategy));
+            profile.time("stats", || print_stats(&vec_a, &vec_b));
+        }
+        "live" => {
+            let mut live = profile.time("inserts", || live_lists(&vec_a, &vec_b));
+            println!("Total distance between paired numbers: {}", live.total_distance());
+            println!("Similarity score: {}", live.similarity_score());
+            let stdin = io::stdin();
+            profile.time("updates", || apply_updates(&mut live, stdin.lock()))?;
+            println!("Left list: {} IDs, right list: {} IDs", live.len_left(), live.len_right());
+        }
+        _ => {
+            eprintln!("Unknown command: {}", command);
+            std::process::exit(1);
+        }
+    }
+
+    if options.profile {
+        profile.print();
+    }
+
+    Ok(())
+}
+
+// How the lists get sorted and counted, all of them give the same results
+#[derive(Clone, Copy)]
+enum Strategy {
+    General,
+    // Counting sort and a dense frequency array when the IDs span a small range
+    Dense(dense::ValueRange),
+    // Chunks sorted and counted on this many threads
+    Sharded(usize),
+}
+
+// Sort both vectors
+fn sort_lists(vec_a: &mut [i32], vec_b: &mut [i32], strategy: Strategy) {
+    match strategy {
+        Strategy::General => {
+            vec_a.sort();
+            vec_b.sort();
+        }
+        Strategy::Dense(range) => {
+            dense::counting_sort(vec_a, range);
+            dense::counting_sort(vec_b, range);
+        }
+        Strategy::Sharded(threads) => {
+            sharded::sort(vec_a, threads);
+            sharded::sort(vec_b, threads);
+        }
+    }
+}
+
+fn similarity(vec_a: &[i32], vec_b: &[i32], strategy: Strategy) -> i64 {
+    match strategy {
+        Strategy::General => lists::similarity_score(vec_a, &lists::frequency_map(vec_b)),
+        Strategy::Dense(range) => dense::similarity_score(vec_a, &dense::counts(vec_b, range), range),
+        Strategy::Sharded(threads) => lists::similarity_score(vec_a, &sharded::frequency_map(vec_b, threads)),
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut filename = None;
+    let mut input = InputOptions::default();
+    let mut memory_cap = 256 * 1024 * 1024;
+    let mut tolerance = 0;
+    let mut weighted = false;
+    let mut threads = 1;
+    let mut profile = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--mem-cap" => {
+                let value = iter.next().ok_or("--mem-cap needs a value")?;
+                memory_cap = parse_size(value).ok_or(format!("invalid memory cap: {}", value))?;
+                if memory_cap < external::MIN_MEMORY_CAP {
+                    return Err(format!("memory cap must be at least {} bytes", external::MIN_MEMORY_CAP));
+                }
+            }
+            "--format" => {
+                let value = iter.next().ok_or("--format needs a value")?;
+                input.format = InputFormat::parse(value).ok_or(format!("unknown format: {}", value))?;
+            }
+            "--delimiter" => {
+                let value = iter.next().ok_or("--delimiter needs a value")?;
+                let mut chars = value.chars();
+                input.format = match (chars.next(), chars.next()) {
+                    (Some(delimiter), None) => InputFormat::Delimited(delimiter),
+                    _ => return Err(format!("delimiter must be a single character: {}", value)),
+                };
+            }
+            "--header" => input.header = true,
+            "--columns" => {
+                let value = iter.next().ok_or("--columns needs a value")?;
+                let (left, right) = value.split_once(',')
+                    .ok_or(format!("--columns expects LEFT,RIGHT: {}", value))?;
+                input.left = Column::parse(left);
+                input.right = Column::parse(right);
+            }
+            "--tolerance" => {
+                let value = iter.next().ok_or("--tolerance needs a value")?;
+                tolerance = value.parse().map_err(|_| format!("invalid tolerance: {}", value))?;
+            }
+            "--weighted" => weighted = true,
+            "--threads" => {
+                let value = iter.next().ok_or("--threads needs a value")?;
+                threads = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid thread count: {}", value)),
+                };
+            }
+            "--profile" => profile = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => filename = Some(arg.clone()),
+        }
+    }
+
+    Ok(Options {
+        filename: filename.ok_or("missing filename")?,
+        input,
+        memory_cap,
+        tolerance,
+        weighted,
+        threads,
+        profile,
+    })
+}
+
+// Byte count with an optional K, M or G suffix
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, scale) = match value.chars().last()?.to_ascii_uppercase() {
+        'K' => (&value[..value.len() - 1], 1 << 10),
+        'M' => (&value[..value.len() - 1], 1 << 20),
+        'G' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(scale)
+}
+
+fn print_stats(sorted_a: &[i32], sorted_b: &[i32]) {
+    let frequency_a = lists::frequency_map(sorted_a);
+    let frequency_map = lists::frequency_map(sorted_b);
+
+    for (name, sorted, frequencies) in [("Left", sorted_a, &frequency_a), ("Right", sorted_b, &frequency_map)] {
+        match stats::list_stats(sorted, frequencies) {
+            Some(list) => {
+                println!("{} list: {} IDs, min {}, max {}, median {}", name, list.count, list.min, list.max, list.median);
+                println!("  mode: {} ({} times)", list.mode, list.mode_count);
+                println!("  duplicates ({}): {}", list.duplicates.len(), format_counts(&list.duplicates));
+            }
+            None => println!("{} list: empty", name),
+        }
+    }
+
+    let left_only = stats::multiset_difference(&frequency_a, &frequency_map);
+    let right_only = stats::multiset_difference(&frequency_map, &frequency_a);
+    println!("Left not in right ({}): {}", left_only.iter().map(|&(_, c)| c).sum::<i32>(), format_counts(&left_only));
+    println!("Right not in left ({}): {}", right_only.iter().map(|&(_, c)| c).sum::<i32>(), format_counts(&right_only));
+
+    println!("Jaccard similarity: {:.6}", stats::jaccard(&frequency_a, &frequency_map));
+    println!("Cosine similarity: {:.6}", stats::cosine(&frequency_a, &frequency_map));
+}
+
+// "ID xCOUNT" pairs separated by spaces
+fn format_counts(counts: &[(i32, i32)]) -> String {
+    counts.iter()
+        .map(|(num, count)| format!("{}x{}", num, count))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Both lists inserted one ID at a time, the totals kept up to date throughout
+fn live_lists(vec_a: &[i32], vec_b: &[i32]) -> LiveLocationLists {
+    let mut live = LiveLocationLists::new();
+    for &num in vec_a {
+        live.insert_left(num);
+    }
+    for &num in vec_b {
+        live.insert_right(num);
+    }
+    live
+}
+
+// Each update applied as it comes in, followed by the new totals. Lines that
+// aren't updates and removals of IDs not in the list are reported and skipped.
+fn apply_updates<R: BufRead>(live: &mut LiveLocationLists, updates: R) -> io::Result<()> {
+    for (i, line) in updates.lines().enumerate() {
+        let line = line?;
+        let mut parts = line.split_whitespace();
+        let (op, value) = match (parts.next(), parts.next().map(str::parse::<i32>), parts.next()) {
+            (None, _, _) => continue,
+            (Some(op), Some(Ok(value)), None) => (op, value),
+            _ => {
+                eprintln!("Update {}: expected +L, -L, +R or -R and an ID: {}", i + 1, line);
+                continue;
+            }
+        };
+        let applied = match op {
+            "+L" => {
+                live.insert_left(value);
+                true
+            }
+            "+R" => {
+                live.insert_right(value);
+                true
+            }
+            "-L" => live.remove_left(value),
+            "-R" => live.remove_right(value),
+            _ => {
+                eprintln!("Update {}: unknown operation {}", i + 1, op);
+                continue;
+            }
+        };
+        if !applied {
+            eprintln!("Update {}: {} is not in the list", i + 1, value);
+            continue;
+        }
+        println!("{} {}: distance {}, similarity {}", op, value, live.total_distance(), live.similarity_score());
+    }
+    Ok(())
+}
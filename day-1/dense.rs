@@ -0,0 +1,115 @@
+// Fast path for lists whose IDs fall in a small range, e.g. the bounded
+// 5-digit location IDs: counting sort instead of a comparison sort and a
+// dense array instead of the frequency HashMap.
+
+// Largest span of values (max - min + 1) handled with dense arrays
+pub const DENSE_SPAN_LIMIT: usize = 1 << 20;
+
+// The span may also be at most this many times the number of values, past
+// that scanning the count array costs more than sorting the values
+pub const DENSE_SPAN_FACTOR: usize = 8;
+
+#[derive(Clone, Copy)]
+pub struct ValueRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl ValueRange {
+    pub fn span(&self) -> usize {
+        (self.max as i64 - self.min as i64 + 1) as usize
+    }
+
+    fn index(&self, value: i32) -> usize {
+        (value as i64 - self.min as i64) as usize
+    }
+}
+
+// Range covering both lists, if it is small enough for the dense path
+// both on its own and next to the length of the lists
+pub fn dense_range(vec_a: &[i32], vec_b: &[i32]) -> Option<ValueRange> {
+    let mut values = vec_a.iter().chain(vec_b.iter());
+    let first = *values.next()?;
+    let range = values.fold(ValueRange { min: first, max: first }, |range, &num| ValueRange {
+        min: range.min.min(num),
+        max: range.max.max(num),
+    });
+    let values = vec_a.len() + vec_b.len();
+    if range.span() <= DENSE_SPAN_LIMIT && range.span() <= DENSE_SPAN_FACTOR * values {
+        Some(range)
+    } else {
+        None
+    }
+}
+
+pub fn counts(values: &[i32], range: ValueRange) -> Vec<i32> {
+    let mut counts = vec![0; range.span()];
+    for &num in values {
+        counts[range.index(num)] += 1;
+    }
+    counts
+}
+
+// Sort in place by rewriting the values from their counts
+pub fn counting_sort(values: &mut [i32], range: ValueRange) {
+    let counts = counts(values, range);
+    let mut i = 0;
+    for (offset, &count) in counts.iter().enumerate() {
+        let num = range.min + offset as i32;
+        for slot in &mut values[i..i + count as usize] {
+            *slot = num;
+        }
+        i += count as usize;
+    }
+}
+
+pub fn similarity_score(vec_a: &[i32], frequencies: &[i32], range: ValueRange) -> i64 {
+    vec_a.iter()
+        .map(|&num| num as i64 * frequencies[range.index(num)] as i64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lists;
+    use crate::rng::XorShift;
+
+    // Random lists around zero, so negative IDs show up, have to sort and
+    // score the same as the comparison sort and the HashMap
+    #[test]
+    fn matches_general_path() {
+        let mut rng = XorShift(0x2024_1203);
+        for round in 0..200 {
+            let len = 1 + rng.below(300);
+            let span = 1 + rng.below(4 * len);
+            let mut vec_a: Vec<i32> = (0..len).map(|_| rng.below(span) as i32 - span as i32 / 2).collect();
+            let vec_b: Vec<i32> = (0..len).map(|_| rng.below(span) as i32 - span as i32 / 2).collect();
+
+            let range = dense_range(&vec_a, &vec_b).unwrap_or_else(|| panic!("round {}: span {} not dense", round, span));
+            let mut expected = vec_a.clone();
+            expected.sort();
+            let frequencies = counts(&vec_b, range);
+            assert_eq!(
+                similarity_score(&vec_a, &frequencies, range),
+                lists::similarity_score(&vec_a, &lists::frequency_map(&vec_b)),
+                "similarity in round {}",
+                round
+            );
+            counting_sort(&mut vec_a, range);
+            assert_eq!(vec_a, expected, "sorted list in round {}", round);
+        }
+    }
+
+    #[test]
+    fn falls_back_on_wide_spans() {
+        assert!(dense_range(&[], &[]).is_none());
+        // Within the span limit, but far wider than six values need
+        assert!(dense_range(&[-500_000, 3, 7], &[500_000, 1, 2]).is_none());
+        let wide = DENSE_SPAN_LIMIT as i32;
+        let many: Vec<i32> = (0..=wide).collect();
+        assert!(dense_range(&many, &many).is_none(), "span past DENSE_SPAN_LIMIT");
+        let range = dense_range(&[-3, 4], &[0, 10]).expect("a narrow span is dense");
+        assert_eq!((range.min, range.max, range.span()), (-3, 10, 14));
+    }
+}
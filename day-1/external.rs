@@ -0,0 +1,251 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::input::{InputOptions, RowParser};
+use crate::lists;
+
+// Buffer size of every run reader and writer
+const IO_BUF: usize = 8 * 1024;
+
+pub const MIN_MEMORY_CAP: usize = 64 * 1024;
+
+// Most runs merged at once per column. Both columns are merged together, so
+// twice this many files are open, which has to stay under the descriptor limit.
+const MAX_FAN_IN: usize = 256;
+
+pub struct ExternalTotals {
+    pub rows: u64,
+    pub runs: usize,
+    pub merge_passes: usize,
+    pub distance: i64,
+    pub similarity: i64,
+}
+
+/// Out-of-core version of the distance and similarity computation.
+///
+/// Both columns are collected into runs that fit `memory_cap` bytes, sorted
+/// and spilled to a temp directory. The runs are then merged in lockstep for
+/// the distance and merge-joined for the similarity score.
+pub fn process<P>(filename: P, input: &InputOptions, memory_cap: usize) -> io::Result<ExternalTotals>
+where P: AsRef<Path> {
+    let memory_cap = memory_cap.max(MIN_MEMORY_CAP);
+    let spill = SpillDir::create()?;
+    let mut parser = RowParser::new(input)?;
+
+    // Two i32 columns per row, keeping room for the line reader
+    let run_rows = (memory_cap - 2 * IO_BUF) / 8;
+    let mut vec_a: Vec<i32> = Vec::with_capacity(run_rows);
+    let mut vec_b: Vec<i32> = Vec::with_capacity(run_rows);
+    let mut runs_a: Vec<PathBuf> = Vec::new();
+    let mut runs_b: Vec<PathBuf> = Vec::new();
+    let mut rows = 0;
+
+    for line in lists::read_lines(filename)? {
+        if let Some((num_a, num_b)) = parser.parse(&line?)? {
+            vec_a.push(num_a);
+            vec_b.push(num_b);
+            rows += 1;
+            if vec_a.len() == run_rows {
+                runs_a.push(spill.write_run(&mut vec_a)?);
+                runs_b.push(spill.write_run(&mut vec_b)?);
+            }
+        }
+    }
+    if !vec_a.is_empty() {
+        runs_a.push(spill.write_run(&mut vec_a)?);
+        runs_b.push(spill.write_run(&mut vec_b)?);
+    }
+    drop(vec_a);
+    drop(vec_b);
+    let runs = runs_a.len();
+
+    // Both columns are merged at the same time, so each side gets half the cap
+    let fan_in = (memory_cap / (2 * IO_BUF) - 1).clamp(2, MAX_FAN_IN);
+    let mut merge_passes = 0;
+    while runs_a.len() > fan_in {
+        runs_a = spill.merge_runs(runs_a, fan_in)?;
+        runs_b = spill.merge_runs(runs_b, fan_in)?;
+        merge_passes += 1;
+    }
+
+    // Total distance between paired numbers. Its mergers are dropped before
+    // the similarity pass opens its own, so only 2 * fan_in readers are open
+    let distance = {
+        let mut left = Merger::open(&runs_a)?;
+        let mut right = Merger::open(&runs_b)?;
+        let mut distance = 0;
+        while let (Some(a), Some(b)) = (left.next()?, right.next()?) {
+            distance += (a as i64 - b as i64).abs();
+        }
+        distance
+    };
+
+    // Similarity score as a merge join over the two sorted columns
+    let mut left = Merger::open(&runs_a)?;
+    let mut right = Merger::open(&runs_b)?;
+    let mut similarity = 0;
+    let mut a = left.next()?;
+    let mut b = right.next()?;
+    while let (Some(x), Some(y)) = (a, b) {
+        if x < y {
+            a = left.next()?;
+        } else if x > y {
+            b = right.next()?;
+        } else {
+            let mut count_a = 0;
+            while a == Some(x) {
+                count_a += 1;
+                a = left.next()?;
+            }
+            let mut count_b = 0;
+            while b == Some(x) {
+                count_b += 1;
+                b = right.next()?;
+            }
+            similarity += x as i64 * count_a * count_b;
+        }
+    }
+
+    Ok(ExternalTotals { rows, runs, merge_passes, distance, similarity })
+}
+
+// Temp directory holding the spilled runs, removed again on drop
+struct SpillDir {
+    path: PathBuf,
+    next_run: Cell<usize>,
+}
+
+impl SpillDir {
+    fn create() -> io::Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        let path = std::env::temp_dir().join(format!("day1-runs-{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&path)?;
+        Ok(SpillDir { path, next_run: Cell::new(0) })
+    }
+
+    fn new_run(&self) -> io::Result<(PathBuf, BufWriter<File>)> {
+        let id = self.next_run.get();
+        self.next_run.set(id + 1);
+        let path = self.path.join(format!("run-{}.bin", id));
+        let writer = BufWriter::with_capacity(IO_BUF, File::create(&path)?);
+        Ok((path, writer))
+    }
+
+    // Sort the buffer, write it out and leave it empty for the next run
+    fn write_run(&self, values: &mut Vec<i32>) -> io::Result<PathBuf> {
+        values.sort_unstable();
+        let (path, mut writer) = self.new_run()?;
+        for &value in values.iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()?;
+        values.clear();
+        Ok(path)
+    }
+
+    fn merge_runs(&self, runs: Vec<PathBuf>, fan_in: usize) -> io::Result<Vec<PathBuf>> {
+        let mut merged = Vec::new();
+        for group in runs.chunks(fan_in) {
+            let mut merger = Merger::open(group)?;
+            let (path, mut writer) = self.new_run()?;
+            while let Some(value) = merger.next()? {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.flush()?;
+            merged.push(path);
+        }
+        for run in &runs {
+            fs::remove_file(run)?;
+        }
+        Ok(merged)
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+struct RunReader {
+    reader: BufReader<File>,
+}
+
+impl RunReader {
+    fn next(&mut self) -> io::Result<Option<i32>> {
+        let mut bytes = [0u8; 4];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(i32::from_le_bytes(bytes))),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// k-way merge of sorted runs
+struct Merger {
+    readers: Vec<RunReader>,
+    heap: BinaryHeap<Reverse<(i32, usize)>>,
+}
+
+impl Merger {
+    fn open(runs: &[PathBuf]) -> io::Result<Self> {
+        let mut readers = Vec::with_capacity(runs.len());
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (idx, run) in runs.iter().enumerate() {
+            let mut reader = RunReader { reader: BufReader::with_capacity(IO_BUF, File::open(run)?) };
+            if let Some(value) = reader.next()? {
+                heap.push(Reverse((value, idx)));
+            }
+            readers.push(reader);
+        }
+        Ok(Merger { readers, heap })
+    }
+
+    fn next(&mut self) -> io::Result<Option<i32>> {
+        let Reverse((value, idx)) = match self.heap.pop() {
+            Some(top) => top,
+            None => return Ok(None),
+        };
+        if let Some(following) = self.readers[idx].next()? {
+            self.heap.push(Reverse((following, idx)));
+        }
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    // At the smallest cap the rows spill into many runs that need merge
+    // passes, and the totals still have to match the in-memory computation
+    #[test]
+    fn matches_in_memory_computation() {
+        let mut rng = XorShift(0x2024_1202);
+        let rows = 50_000;
+        let (mut left, mut right): (Vec<i32>, Vec<i32>) = (0..rows)
+            .map(|_| (rng.below(20_000) as i32 - 10_000, rng.below(20_000) as i32 - 10_000))
+            .unzip();
+
+        let path = std::env::temp_dir().join(format!("day1-external-test-{}.txt", std::process::id()));
+        let text: String = left.iter().zip(&right).map(|(a, b)| format!("{}   {}\n", a, b)).collect();
+        fs::write(&path, text).expect("writing the test input");
+        let totals = process(&path, &InputOptions::default(), MIN_MEMORY_CAP);
+        fs::remove_file(&path).expect("removing the test input");
+        let totals = totals.expect("external processing");
+
+        assert_eq!(totals.rows, rows as u64);
+        assert!(totals.merge_passes > 0, "{} runs needed no merge pass", totals.runs);
+        left.sort();
+        right.sort();
+        assert_eq!(totals.distance, lists::total_distance(&left, &right));
+        assert_eq!(totals.similarity, lists::similarity_score(&left, &lists::frequency_map(&right)));
+    }
+}
@@ -0,0 +1,126 @@
+// Similarity score that tolerates transcription noise: every left ID counts
+// the right IDs within +-tolerance instead of exact matches only. Both lists
+// must be sorted so the window can be tracked with pointers that only move
+// forward.
+
+/// Sum of `a * (number of right IDs in [a - tolerance, a + tolerance])`.
+/// With a tolerance of 0 this is the exact similarity score.
+pub fn similarity_score(sorted_a: &[i32], sorted_b: &[i32], tolerance: u32) -> i64 {
+    let tolerance = tolerance as i64;
+    let mut lo = 0;
+    let mut hi = 0;
+    let mut score = 0;
+
+    for &num in sorted_a {
+        let num = num as i64;
+        while lo < sorted_b.len() && (sorted_b[lo] as i64) < num - tolerance {
+            lo += 1;
+        }
+        while hi < sorted_b.len() && (sorted_b[hi] as i64) <= num + tolerance {
+            hi += 1;
+        }
+        score += num * (hi - lo) as i64;
+    }
+
+    score
+}
+
+/// Like `similarity_score`, but a right ID at distance d only counts
+/// (tolerance + 1 - d) / (tolerance + 1), so exact matches weigh 1.
+pub fn weighted_similarity_score(sorted_a: &[i32], sorted_b: &[i32], tolerance: u32) -> f64 {
+    let tolerance = tolerance as i64;
+
+    // prefix[i] is the sum of the first i right IDs
+    let mut prefix: Vec<i64> = Vec::with_capacity(sorted_b.len() + 1);
+    prefix.push(0);
+    for &num in sorted_b {
+        prefix.push(prefix[prefix.len() - 1] + num as i64);
+    }
+
+    // Window is [lo, hi), with [lo, mid) at or below num and [mid, hi) above
+    let mut lo = 0;
+    let mut mid = 0;
+    let mut hi = 0;
+    let mut score = 0.0;
+
+    for &num in sorted_a {
+        let num = num as i64;
+        while lo < sorted_b.len() && (sorted_b[lo] as i64) < num - tolerance {
+            lo += 1;
+        }
+        mid = mid.max(lo);
+        while mid < sorted_b.len() && (sorted_b[mid] as i64) <= num {
+            mid += 1;
+        }
+        hi = hi.max(mid);
+        while hi < sorted_b.len() && (sorted_b[hi] as i64) <= num + tolerance {
+            hi += 1;
+        }
+
+        // sum of (tolerance + 1 - |num - b|) over the window
+        let below = (mid - lo) as i64;
+        let above = (hi - mid) as i64;
+        let closeness = below * (tolerance + 1 - num) + (prefix[mid] - prefix[lo])
+            + above * (tolerance + 1 + num) - (prefix[hi] - prefix[mid]);
+
+        score += num as f64 * closeness as f64 / (tolerance + 1) as f64;
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lists;
+    use crate::rng::XorShift;
+
+    fn sorted_list(rng: &mut XorShift, len: usize) -> Vec<i32> {
+        // A narrow range around zero, so there are duplicates and negative IDs
+        let mut list: Vec<i32> = (0..len).map(|_| rng.below(60) as i32 - 20).collect();
+        list.sort();
+        list
+    }
+
+    // Every pair compared directly
+    fn naive_scores(sorted_a: &[i32], sorted_b: &[i32], tolerance: u32) -> (i64, f64) {
+        let tolerance = tolerance as i64;
+        let mut score = 0;
+        let mut weighted = 0.0;
+        for &a in sorted_a {
+            let mut count = 0;
+            let mut closeness = 0;
+            for &b in sorted_b {
+                let d = (a as i64 - b as i64).abs();
+                if d <= tolerance {
+                    count += 1;
+                    closeness += tolerance + 1 - d;
+                }
+            }
+            score += a as i64 * count;
+            weighted += a as f64 * closeness as f64 / (tolerance + 1) as f64;
+        }
+        (score, weighted)
+    }
+
+    #[test]
+    fn matches_naive_computation() {
+        let mut rng = XorShift(0x2024_1204);
+        for round in 0..500 {
+            let (len_a, len_b) = (rng.below(40), rng.below(40));
+            let sorted_a = sorted_list(&mut rng, len_a);
+            let sorted_b = sorted_list(&mut rng, len_b);
+            let tolerance = rng.below(6) as u32;
+
+            let (score, weighted) = naive_scores(&sorted_a, &sorted_b, tolerance);
+            assert_eq!(similarity_score(&sorted_a, &sorted_b, tolerance), score, "round {}", round);
+            let found = weighted_similarity_score(&sorted_a, &sorted_b, tolerance);
+            assert!((found - weighted).abs() <= 1e-9 * weighted.abs().max(1.0), "round {}: {} != {}", round, found, weighted);
+
+            // Without tolerance both are the exact similarity score
+            let exact = lists::similarity_score(&sorted_a, &lists::frequency_map(&sorted_b));
+            assert_eq!(similarity_score(&sorted_a, &sorted_b, 0), exact, "round {}", round);
+            assert_eq!(weighted_similarity_score(&sorted_a, &sorted_b, 0), exact as f64, "round {}", round);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::io;
+
+// How the two location columns are laid out in the input file
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    // Columns separated by any run of whitespace, the original format
+    Whitespace,
+    // Fields separated by a single delimiter, optionally "quoted" with ""
+    // escaping a quote inside a quoted field (CSV is ',' and TSV is '\t')
+    Delimited(char),
+}
+
+#[derive(Clone)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Clone)]
+pub struct InputOptions {
+    pub format: InputFormat,
+    pub header: bool,
+    pub left: Column,
+    pub right: Column,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        InputOptions {
+            format: InputFormat::Whitespace,
+            header: false,
+            left: Column::Index(0),
+            right: Column::Index(1),
+        }
+    }
+}
+
+impl InputFormat {
+    pub fn parse(name: &str) -> Option<InputFormat> {
+        match name {
+            "whitespace" => Some(InputFormat::Whitespace),
+            "csv" => Some(InputFormat::Delimited(',')),
+            "tsv" => Some(InputFormat::Delimited('\t')),
+            _ => None,
+        }
+    }
+}
+
+impl Column {
+    // A plain number selects by index, anything else by header name
+    pub fn parse(spec: &str) -> Column {
+        match spec.parse() {
+            Ok(index) => Column::Index(index),
+            Err(_) => Column::Name(spec.to_string()),
+        }
+    }
+}
+
+// Turns input lines into (left, right) pairs, consuming the header first
+pub struct RowParser {
+    options: InputOptions,
+    columns: Option<(usize, usize)>,
+}
+
+impl RowParser {
+    pub fn new(options: &InputOptions) -> io::Result<Self> {
+        let columns = match (&options.left, &options.right) {
+            (&Column::Index(left), &Column::Index(right)) if !options.header => Some((left, right)),
+            _ if !options.header => return Err(invalid("selecting columns by name needs --header")),
+            _ => None,
+        };
+        Ok(RowParser { options: options.clone(), columns })
+    }
+
+    /// Returns the pair on a data line and None on the header or on lines
+    /// that don't hold two integers in the selected columns
+    pub fn parse(&mut self, line: &str) -> io::Result<Option<(i32, i32)>> {
+        let fields = split_fields(line, self.options.format);
+
+        let (left, right) = match self.columns {
+            Some(columns) => columns,
+            None => {
+                let left = resolve(&self.options.left, &fields)?;
+                let right = resolve(&self.options.right, &fields)?;
+                self.columns = Some((left, right));
+                return Ok(None);
+            }
+        };
+
+        match (fields.get(left), fields.get(right)) {
+            (Some(a), Some(b)) => match (a.trim().parse(), b.trim().parse()) {
+                // Convert strings to integers
+                (Ok(num_a), Ok(num_b)) => Ok(Some((num_a, num_b))),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+fn resolve(column: &Column, header: &[String]) -> io::Result<usize> {
+    match column {
+        &Column::Index(index) => Ok(index),
+        Column::Name(name) => header.iter()
+            .position(|field| field.trim() == name)
+            .ok_or_else(|| invalid(&format!("no column named '{}' in the header", name))),
+    }
+}
+
+pub fn split_fields(line: &str, format: InputFormat) -> Vec<String> {
+    let delimiter = match format {
+        InputFormat::Whitespace => return line.split_whitespace().map(String::from).collect(),
+        InputFormat::Delimited(delimiter) => delimiter,
+    };
+
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(ch) = chars.next() {
+        if quoted {
+            if ch == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(ch);
+            }
+        } else if ch == delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else if ch == '"' && field.trim().is_empty() {
+            field.clear();
+            quoted = true;
+        } else {
+            field.push(ch);
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(format: InputFormat, header: bool, left: &str, right: &str) -> InputOptions {
+        InputOptions { format, header, left: Column::parse(left), right: Column::parse(right) }
+    }
+
+    fn rows(options: &InputOptions, lines: &[&str]) -> Vec<Option<(i32, i32)>> {
+        let mut parser = RowParser::new(options).expect("valid options");
+        lines.iter().map(|line| parser.parse(line).expect("known columns")).collect()
+    }
+
+    #[test]
+    fn csv_with_header() {
+        let options = options(InputFormat::Delimited(','), true, "right", "left");
+        let lines = ["id,left,right", "1,3,4", "2, 4 ,\"3\"", "3,x,5"];
+        assert_eq!(rows(&options, &lines), [None, Some((4, 3)), Some((3, 4)), None]);
+    }
+
+    #[test]
+    fn tsv_with_quotes() {
+        let fields = split_fields("\"a\tb\"\t\"say \"\"hi\"\"\"\t7", InputFormat::Delimited('\t'));
+        assert_eq!(fields, ["a\tb", "say \"hi\"", "7"]);
+
+        let options = options(InputFormat::Delimited('\t'), false, "1", "2");
+        assert_eq!(rows(&options, &["\"x\"\t\"12\"\t-5"]), [Some((12, -5))]);
+    }
+
+    #[test]
+    fn semicolon_delimiter() {
+        assert_eq!(split_fields("1;\"2;3\";;4", InputFormat::Delimited(';')), ["1", "2;3", "", "4"]);
+        let options = options(InputFormat::Delimited(';'), false, "0", "3");
+        assert_eq!(rows(&options, &["10;a;b;20", "10;20"]), [Some((10, 20)), None]);
+    }
+
+    #[test]
+    fn unknown_column_names() {
+        let mut parser = RowParser::new(&options(InputFormat::Delimited(','), true, "left", "missing")).expect("valid options");
+        let error = parser.parse("left,right").expect_err("no such column");
+        assert_eq!(error.to_string(), "no column named 'missing' in the header");
+
+        let error = RowParser::new(&options(InputFormat::Whitespace, false, "left", "1")).err().expect("names need a header");
+        assert_eq!(error.to_string(), "selecting columns by name needs --header");
+    }
+}
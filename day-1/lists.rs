@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::input::{InputOptions, RowParser};
+
+// Read both location columns, skipping lines that don't hold two integers
+pub fn load_lists<P>(filename: P, input: &InputOptions) -> io::Result<(Vec<i32>, Vec<i32>)>
+where P: AsRef<Path> {
+    let mut vec_a: Vec<i32> = Vec::new();
+    let mut vec_b: Vec<i32> = Vec::new();
+    let mut parser = RowParser::new(input)?;
+
+    for line in read_lines(filename)? {
+        if let Some((num_a, num_b)) = parser.parse(&line?)? {
+            vec_a.push(num_a);
+            vec_b.push(num_b);
+        }
+    }
+
+    Ok((vec_a, vec_b))
+}
+
+// Total distance between paired numbers, both vectors must already be sorted
+pub fn total_distance(vec_a: &[i32], vec_b: &[i32]) -> i64 {
+    vec_a.iter()
+        .zip(vec_b.iter())
+        .map(|(&a, &b)| (a as i64 - b as i64).abs())
+        .sum()
+}
+
+// Calculate frequency of numbers in vec_b
+pub fn frequency_map(vec_b: &[i32]) -> HashMap<i32, i32> {
+    let mut frequency_map: HashMap<i32, i32> = HashMap::new();
+    for &num in vec_b {
+        *frequency_map.entry(num).or_insert(0) += 1;
+    }
+    frequency_map
+}
+
+pub fn similarity_score(vec_a: &[i32], frequency_map: &HashMap<i32, i32>) -> i64 {
+    vec_a.iter()
+        .map(|&num| num as i64 * *frequency_map.get(&num).unwrap_or(&0) as i64)
+        .sum()
+}
+
+pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+where P: AsRef<Path> {
+    let file = File::open(filename)?;
+    Ok(io::BufReader::new(file).lines())
+}
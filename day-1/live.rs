@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+// The sorted-pair distance equals the area between the two counting
+// functions F_left(x) - F_right(x) (up to the n-th smallest value of the
+// longer list, see `total_distance`). We keep every distinct value in sorted
+// blocks of roughly sqrt(n) entries, each entry storing that difference D
+// and the gap to the next distinct value, so an update only rebuilds one
+// block and shifts D for the blocks after it in O(1) each.
+
+const MIN_BLOCK: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    value: i32,
+    left: i64,
+    right: i64,
+    // prefix difference of left and right counts up to this value
+    d: i64,
+    // distance to the next distinct value, 0 for the last one
+    gap: i64,
+}
+
+#[derive(Default)]
+struct Block {
+    entries: Vec<Entry>,
+    // pending shift of every `d` in the block
+    lazy: i64,
+    gap_by_d: HashMap<i64, i64>,
+    total_gap: i64,
+    // gap weight of the entries with d + lazy < 0
+    neg_gap: i64,
+    // sum of gap * |d + lazy|
+    abs_sum: i64,
+    left: i64,
+    right: i64,
+    // sum of value * count on each side
+    left_sum: i64,
+    right_sum: i64,
+}
+
+impl Block {
+    fn first_value(&self) -> i32 {
+        self.entries[0].value
+    }
+
+    fn last_value(&self) -> i32 {
+        self.entries[self.entries.len() - 1].value
+    }
+
+    fn rebuild(&mut self, prefix: i64, next: Option<i32>) {
+        self.lazy = 0;
+        self.gap_by_d.clear();
+        self.total_gap = 0;
+        self.neg_gap = 0;
+        self.abs_sum = 0;
+        self.left = 0;
+        self.right = 0;
+        self.left_sum = 0;
+        self.right_sum = 0;
+
+        let mut d = prefix;
+        for i in 0..self.entries.len() {
+            let next_value = self.entries.get(i + 1).map(|e| e.value).or(next);
+            let e = &mut self.entries[i];
+            e.gap = next_value.map_or(0, |n| n as i64 - e.value as i64);
+            d += e.left - e.right;
+            e.d = d;
+
+            *self.gap_by_d.entry(d).or_insert(0) += e.gap;
+            self.total_gap += e.gap;
+            if d < 0 {
+                self.neg_gap += e.gap;
+            }
+            self.abs_sum += e.gap * d.abs();
+            self.left += e.left;
+            self.right += e.right;
+            self.left_sum += e.value as i64 * e.left;
+            self.right_sum += e.value as i64 * e.right;
+        }
+    }
+
+    // Add +1 or -1 to every d in the block without touching the entries
+    fn shift(&mut self, delta: i64) {
+        match delta {
+            1 => {
+                // |d + 1| - |d| is +1 for d >= 0 and -1 for d < 0
+                self.abs_sum += self.total_gap - 2 * self.neg_gap;
+                self.neg_gap -= self.gap_by_d.get(&(-self.lazy - 1)).unwrap_or(&0);
+                self.lazy += 1;
+            }
+            -1 => {
+                let zero = *self.gap_by_d.get(&(-self.lazy)).unwrap_or(&0);
+                let non_pos = self.neg_gap + zero;
+                self.abs_sum += non_pos - (self.total_gap - non_pos);
+                self.neg_gap += zero;
+                self.lazy -= 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Both location lists kept in a form where single inserts and removals
+/// update the sorted-pair distance and similarity score in O(sqrt(n)).
+#[derive(Default)]
+pub struct LiveLocationLists {
+    blocks: Vec<Block>,
+    left_counts: HashMap<i32, i64>,
+    right_counts: HashMap<i32, i64>,
+    len_left: i64,
+    len_right: i64,
+    distinct: usize,
+    similarity: i64,
+}
+
+impl LiveLocationLists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len_left(&self) -> usize {
+        self.len_left as usize
+    }
+
+    pub fn len_right(&self) -> usize {
+        self.len_right as usize
+    }
+
+    pub fn insert_left(&mut self, value: i32) {
+        self.similarity += value as i64 * self.right_counts.get(&value).unwrap_or(&0);
+        *self.left_counts.entry(value).or_insert(0) += 1;
+        self.len_left += 1;
+        self.adjust(value, 1, 0);
+    }
+
+    pub fn insert_right(&mut self, value: i32) {
+        self.similarity += value as i64 * self.left_counts.get(&value).unwrap_or(&0);
+        *self.right_counts.entry(value).or_insert(0) += 1;
+        self.len_right += 1;
+        self.adjust(value, 0, 1);
+    }
+
+    /// Removes one occurrence of `value`, returns false if it wasn't in the list
+    pub fn remove_left(&mut self, value: i32) -> bool {
+        if !decrement(&mut self.left_counts, value) {
+            return false;
+        }
+        self.similarity -= value as i64 * self.right_counts.get(&value).unwrap_or(&0);
+        self.len_left -= 1;
+        self.adjust(value, -1, 0);
+        true
+    }
+
+    /// Removes one occurrence of `value`, returns false if it wasn't in the list
+    pub fn remove_right(&mut self, value: i32) -> bool {
+        if !decrement(&mut self.right_counts, value) {
+            return false;
+        }
+        self.similarity -= value as i64 * self.left_counts.get(&value).unwrap_or(&0);
+        self.len_right -= 1;
+        self.adjust(value, 0, -1);
+        true
+    }
+
+    pub fn similarity_score(&self) -> i64 {
+        self.similarity
+    }
+
+    /// Same result as sorting both lists and summing |a - b| over the zipped
+    /// pairs, so the extra elements of the longer list are ignored.
+    ///
+    /// With n = min(len) and t the n-th smallest value of the longer list,
+    /// the distance is the area of |D| left of t plus the part of the shorter
+    /// list lying above t.
+    pub fn total_distance(&self) -> i64 {
+        let n = self.len_left.min(self.len_right);
+        if n == 0 {
+            return 0;
+        }
+        let left_is_longer = self.len_left >= self.len_right;
+        let longer = |left: i64, right: i64| if left_is_longer { left } else { right };
+        let shorter = |left: i64, right: i64| if left_is_longer { right } else { left };
+
+        let mut seen = 0;
+        let mut area = 0;
+        let mut threshold = None;
+        let mut above = 0;
+
+        for block in &self.blocks {
+            match threshold {
+                None => {
+                    let count = longer(block.left, block.right);
+                    if seen + count < n {
+                        seen += count;
+                        area += block.abs_sum;
+                        continue;
+                    }
+                    for e in &block.entries {
+                        match threshold {
+                            None => {
+                                let count = longer(e.left, e.right);
+                                if seen + count >= n {
+                                    threshold = Some(e.value as i64);
+                                } else {
+                                    seen += count;
+                                    area += e.gap * (e.d + block.lazy).abs();
+                                }
+                            }
+                            Some(t) => above += shorter(e.left, e.right) * (e.value as i64 - t),
+                        }
+                    }
+                }
+                // Whole blocks past the threshold in O(1) each
+                Some(t) => above += shorter(block.left_sum, block.right_sum) - t * shorter(block.left, block.right),
+            }
+        }
+
+        area + above
+    }
+
+    fn adjust(&mut self, value: i32, dl: i64, dr: i64) {
+        let delta = dl - dr;
+        if self.blocks.is_empty() {
+            self.blocks.push(Block::default());
+        }
+
+        let bi = self.blocks
+            .partition_point(|b| !b.entries.is_empty() && b.last_value() < value)
+            .min(self.blocks.len() - 1);
+        let prefix: i64 = self.blocks[..bi].iter().map(|b| b.left - b.right).sum();
+
+        let block = &mut self.blocks[bi];
+        match block.entries.binary_search_by_key(&value, |e| e.value) {
+            Ok(pos) => {
+                let e = &mut block.entries[pos];
+                e.left += dl;
+                e.right += dr;
+                if e.left == 0 && e.right == 0 {
+                    block.entries.remove(pos);
+                    self.distinct -= 1;
+                }
+            }
+            Err(pos) => {
+                block.entries.insert(pos, Entry { value, left: dl, right: dr, d: 0, gap: 0 });
+                self.distinct += 1;
+            }
+        }
+
+        for later in &mut self.blocks[bi + 1..] {
+            later.shift(delta);
+        }
+
+        let next = self.blocks.get(bi + 1).map(|b| b.first_value());
+        self.blocks[bi].rebuild(prefix, next);
+
+        let (mut bi, mut prefix) = (bi, prefix);
+        if self.blocks[bi].entries.is_empty() {
+            self.blocks.remove(bi);
+        } else {
+            let target = ((self.distinct as f64).sqrt() as usize).max(MIN_BLOCK);
+            // Merge a block removals have thinned out into a neighbour, so the
+            // number of blocks stays around sqrt(distinct)
+            if self.blocks[bi].entries.len() < target / 2 && self.blocks.len() > 1 {
+                if bi + 1 == self.blocks.len() {
+                    bi -= 1;
+                    prefix -= self.blocks[bi].left - self.blocks[bi].right;
+                }
+                let tail = self.blocks.remove(bi + 1);
+                self.blocks[bi].entries.extend(tail.entries);
+                let next = self.blocks.get(bi + 1).map(|b| b.first_value());
+                self.blocks[bi].rebuild(prefix, next);
+            }
+            if self.blocks[bi].entries.len() > 2 * target {
+                self.split(bi, prefix);
+            }
+        }
+
+        // The gap of the previous block's last value depends on our first one
+        if bi > 0 {
+            let prev = &self.blocks[bi - 1];
+            let prev_prefix = prefix - (prev.left - prev.right);
+            let next = self.blocks.get(bi).map(|b| b.first_value());
+            self.blocks[bi - 1].rebuild(prev_prefix, next);
+        }
+    }
+
+    // Split block `bi` into two halves, `prefix` is the difference of the
+    // counts before it
+    fn split(&mut self, bi: usize, prefix: i64) {
+        let next = self.blocks.get(bi + 1).map(|b| b.first_value());
+        let half = self.blocks[bi].entries.len() / 2;
+        let mut tail = Block {
+            entries: self.blocks[bi].entries.split_off(half),
+            ..Block::default()
+        };
+        let head_next = Some(tail.first_value());
+        self.blocks[bi].rebuild(prefix, head_next);
+        let head = &self.blocks[bi];
+        tail.rebuild(prefix + head.left - head.right, next);
+        self.blocks.insert(bi + 1, tail);
+    }
+}
+
+fn decrement(counts: &mut HashMap<i32, i64>, value: i32) -> bool {
+    match counts.get_mut(&value) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&value);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LiveLocationLists, MIN_BLOCK};
+    use crate::lists;
+    use crate::rng::XorShift;
+
+    // Random inserts and removes, with the live totals compared to the batch
+    // computation after every update
+    #[test]
+    fn matches_batch_computation() {
+        let mut live = LiveLocationLists::new();
+        let mut left: Vec<i32> = Vec::new();
+        let mut right: Vec<i32> = Vec::new();
+        let mut rng = XorShift(0x2024_1201);
+
+        for step in 0..5000 {
+            let is_left = rng.below(2) == 0;
+            let side = if is_left { &mut left } else { &mut right };
+            // Inserts win slightly so the lists grow past a few blocks
+            if side.is_empty() || rng.below(5) < 3 {
+                // Reuse an existing ID half of the time so duplicates show up
+                let value = if !side.is_empty() && rng.chance(50) {
+                    side[rng.below(side.len())]
+                } else {
+                    10_000 + rng.below(90_000) as i32
+                };
+                side.push(value);
+                if is_left { live.insert_left(value) } else { live.insert_right(value) }
+            } else {
+                let value = side.swap_remove(rng.below(side.len()));
+                let removed = if is_left { live.remove_left(value) } else { live.remove_right(value) };
+                assert!(removed, "update {}: {} was not found in the live lists", step, value);
+            }
+            assert!(!live.remove_left(5), "update {}: removed a value that was never inserted", step);
+
+            let mut sorted_a = left.clone();
+            let mut sorted_b = right.clone();
+            sorted_a.sort();
+            sorted_b.sort();
+            assert_eq!(live.total_distance(), lists::total_distance(&sorted_a, &sorted_b), "distance after update {}", step);
+            assert_eq!(
+                live.similarity_score(),
+                lists::similarity_score(&left, &lists::frequency_map(&right)),
+                "similarity after update {}",
+                step
+            );
+            assert_eq!((live.len_left(), live.len_right()), (left.len(), right.len()));
+        }
+    }
+
+    // Removing most of the values has to merge the blocks they leave behind,
+    // or every update walks one block per remaining value
+    #[test]
+    fn thinned_lists_keep_few_blocks() {
+        let mut live = LiveLocationLists::new();
+        let values: Vec<i32> = (0..20_000).map(|i| i * 7).collect();
+        for &value in &values {
+            live.insert_left(value);
+            live.insert_right(value + 3);
+        }
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for (i, &value) in values.iter().enumerate() {
+            if i % 150 == 0 {
+                left.push(value);
+                right.push(value + 3);
+            } else {
+                assert!(live.remove_left(value) && live.remove_right(value + 3));
+            }
+        }
+
+        let target = ((live.distinct as f64).sqrt() as usize).max(MIN_BLOCK);
+        // Every block but the last holds at least target / 2 values
+        assert!(
+            live.blocks.len() <= live.distinct / (target / 2) + 1,
+            "{} blocks for {} distinct values",
+            live.blocks.len(),
+            live.distinct
+        );
+        assert_eq!(live.total_distance(), lists::total_distance(&left, &right));
+    }
+}
@@ -0,0 +1,86 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::thread;
+
+use crate::lists;
+
+// Multi-threaded versions of the sort and the frequency count: the list is
+// split into one chunk per thread and the partial results are combined, so
+// the outcome is identical to the single-threaded path.
+
+fn chunk_size(len: usize, threads: usize) -> usize {
+    len.div_ceil(threads.max(1)).max(1)
+}
+
+// Sort every chunk on its own thread, then k-way merge the sorted chunks
+pub fn sort(values: &mut [i32], threads: usize) {
+    let size = chunk_size(values.len(), threads);
+    thread::scope(|scope| {
+        for chunk in values.chunks_mut(size) {
+            scope.spawn(move || chunk.sort());
+        }
+    });
+
+    let mut heap: BinaryHeap<Reverse<(i32, usize, usize)>> = BinaryHeap::new();
+    let chunks: Vec<&[i32]> = values.chunks(size).collect();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        if let Some(&first) = chunk.first() {
+            heap.push(Reverse((first, idx, 0)));
+        }
+    }
+
+    let mut merged = Vec::with_capacity(values.len());
+    while let Some(Reverse((num, idx, pos))) = heap.pop() {
+        merged.push(num);
+        if let Some(&following) = chunks[idx].get(pos + 1) {
+            heap.push(Reverse((following, idx, pos + 1)));
+        }
+    }
+    values.copy_from_slice(&merged);
+}
+
+// Per-thread frequency maps reduced into the final frequency_map
+pub fn frequency_map(values: &[i32], threads: usize) -> HashMap<i32, i32> {
+    let size = chunk_size(values.len(), threads);
+    let partial_maps: Vec<HashMap<i32, i32>> = thread::scope(|scope| {
+        let handles: Vec<_> = values.chunks(size)
+            .map(|chunk| scope.spawn(move || lists::frequency_map(chunk)))
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().expect("frequency worker panicked"))
+            .collect()
+    });
+
+    let mut frequency_map: HashMap<i32, i32> = HashMap::new();
+    for partial in partial_maps {
+        for (num, count) in partial {
+            *frequency_map.entry(num).or_insert(0) += count;
+        }
+    }
+    frequency_map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    // Random lists with many duplicates, split over more and fewer threads
+    // than there are values, empty ones included
+    #[test]
+    fn matches_single_threaded_path() {
+        let mut rng = XorShift(0x2024_1205);
+        for round in 0..300 {
+            let len = if round % 10 == 0 { 0 } else { rng.below(200) };
+            let values: Vec<i32> = (0..len).map(|_| rng.below(50) as i32 - 25).collect();
+            let threads = 1 + rng.below(2 * len + 2);
+
+            let mut sorted = values.clone();
+            sort(&mut sorted, threads);
+            let mut expected = values.clone();
+            expected.sort();
+            assert_eq!(sorted, expected, "round {} on {} threads", round, threads);
+            assert_eq!(frequency_map(&values, threads), lists::frequency_map(&values), "round {} on {} threads", round, threads);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+// Summary of one location list, `sorted` must be sorted and `frequency_map`
+// built from the same list
+pub struct ListStats {
+    pub count: usize,
+    pub min: i32,
+    pub max: i32,
+    pub median: f64,
+    pub mode: i32,
+    pub mode_count: i32,
+    // (ID, count) for every ID seen more than once, by ID
+    pub duplicates: Vec<(i32, i32)>,
+}
+
+pub fn list_stats(sorted: &[i32], frequency_map: &HashMap<i32, i32>) -> Option<ListStats> {
+    let count = sorted.len();
+    if count == 0 {
+        return None;
+    }
+
+    let median = if count % 2 == 1 {
+        sorted[count / 2] as f64
+    } else {
+        (sorted[count / 2 - 1] as f64 + sorted[count / 2] as f64) / 2.0
+    };
+
+    // Most frequent ID, the smallest one on ties
+    let (&mode, &mode_count) = frequency_map.iter()
+        .max_by(|(a, ca), (b, cb)| ca.cmp(cb).then(b.cmp(a)))?;
+
+    let mut duplicates: Vec<(i32, i32)> = frequency_map.iter()
+        .filter(|(_, &c)| c > 1)
+        .map(|(&num, &c)| (num, c))
+        .collect();
+    duplicates.sort();
+
+    Some(ListStats {
+        count,
+        min: sorted[0],
+        max: sorted[count - 1],
+        median,
+        mode,
+        mode_count,
+        duplicates,
+    })
+}
+
+// IDs occurring more often in `from` than in `other`, with the surplus count
+pub fn multiset_difference(from: &HashMap<i32, i32>, other: &HashMap<i32, i32>) -> Vec<(i32, i32)> {
+    let mut difference: Vec<(i32, i32)> = from.iter()
+        .map(|(&num, &c)| (num, c - other.get(&num).unwrap_or(&0)))
+        .filter(|&(_, surplus)| surplus > 0)
+        .collect();
+    difference.sort();
+    difference
+}
+
+// Weighted Jaccard of the two frequency vectors: sum of min over sum of max
+pub fn jaccard(freq_a: &HashMap<i32, i32>, freq_b: &HashMap<i32, i32>) -> f64 {
+    let mut min_sum = 0i64;
+    let mut max_sum = 0i64;
+    for (num, &ca) in freq_a {
+        let cb = *freq_b.get(num).unwrap_or(&0);
+        min_sum += ca.min(cb) as i64;
+        max_sum += ca.max(cb) as i64;
+    }
+    for (num, &cb) in freq_b {
+        if !freq_a.contains_key(num) {
+            max_sum += cb as i64;
+        }
+    }
+    if max_sum == 0 {
+        0.0
+    } else {
+        min_sum as f64 / max_sum as f64
+    }
+}
+
+pub fn cosine(freq_a: &HashMap<i32, i32>, freq_b: &HashMap<i32, i32>) -> f64 {
+    let dot: i64 = freq_a.iter()
+        .map(|(num, &ca)| ca as i64 * *freq_b.get(num).unwrap_or(&0) as i64)
+        .sum();
+    let norm = |freq: &HashMap<i32, i32>| {
+        (freq.values().map(|&c| c as i64 * c as i64).sum::<i64>() as f64).sqrt()
+    };
+    let norms = norm(freq_a) * norm(freq_b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot as f64 / norms
+    }
+}
@@ -3,50 +3,73 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+mod policy;
+mod rules;
+
+use policy::{Outcome, Policy};
+use rules::{is_valid_without_removal, SafetyRules};
+
+const USAGE: &str = "<filename> [--rules <file>] [--policy skip|strict|lenient|spec]";
+
+struct Options {
+    filename: String,
+    rules: SafetyRules,
+    // Handling of lines that aren't a report of at least two levels
+    policy: Policy,
+}
+
 fn main() -> io::Result<()> {
     // Get command line arguments
     let args: Vec<String> = env::args().collect();
     
     // Check if filename was provided
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
+        eprintln!("Usage: {} {}", args[0], USAGE);
         std::process::exit(1);
     }
 
-    // Get the filename from arguments
-    let filename = &args[1];
-    
+    let options = match parse_options(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: {} {}", args[0], USAGE);
+            std::process::exit(1);
+        }
+    };
     // Read the file line by line
-    let path = Path::new(filename);
+    let path = Path::new(&options.filename);
     let file = File::open(path)?;
     let reader = io::BufReader::new(file);
 
     let mut valid_lines = 0; // Counter for valid sequences
+    // Lines that weren't a proper report, for the summary at the end
+    let mut malformed = Vec::new();
 
     // Process each line
-    for line in reader.lines() {
+    for (idx, line) in reader.lines().enumerate() {
         let line = line?;
-        let numbers: Result<Vec<i32>, _> = line
-            .split_whitespace()
-            .map(|s| s.parse::<i32>())
-            .collect();
-        
-        match numbers {
+        match policy::parse_report(&line) {
             Ok(nums) => {
-                if nums.len() < 2 {
-                    eprintln!("Error: Each line must contain at least 2 numbers");
-                    continue;
-                }
-                
                 // Check if sequence is valid
-                if is_valid_sequence(&nums) {
+                if is_valid_without_removal(&nums, &options.rules) {
                     valid_lines += 1;
                 }
             }
-            Err(e) => {
-                eprintln!("Error parsing line: {}", e);
-                continue;
-            }
+            Err(problem) => match options.policy.outcome(&problem) {
+                Some(Outcome::CountedSafe) => valid_lines += 1,
+                Some(outcome) => malformed.push((idx + 1, problem, outcome)),
+                None => {
+                    eprintln!("Error: line {}, column {}: {}", idx + 1, problem.column(), problem);
+                    std::process::exit(1);
+                }
+            },
+        }
+    }
+
+    if !malformed.is_empty() {
+        eprintln!("{} malformed lines:", malformed.len());
+        for (line_number, problem, outcome) in &malformed {
+            eprintln!("  line {}, column {}: {} ({})", line_number, problem.column(), problem, outcome.name());
         }
     }
 
@@ -54,18 +77,31 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn is_valid_sequence(nums: &[i32]) -> bool {
-    // Check if sequence is increasing
-    let is_increasing = (0..nums.len()-1).all(|i| {
-        let diff = nums[i+1] - nums[i];
-        diff >= 1 && diff <= 3
-    });
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut filename = None;
+    let mut rules = SafetyRules::default();
+    let mut policy = Policy::Skip;
 
-    // Check if sequence is decreasing
-    let is_decreasing = (0..nums.len()-1).all(|i| {
-        let diff = nums[i] - nums[i+1];
-        diff >= 1 && diff <= 3
-    });
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Step limits and direction come from the rules file when one is given
+            "--rules" => {
+                let path = iter.next().ok_or("--rules needs a file")?;
+                rules = SafetyRules::load(path).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "--policy" => {
+                let value = iter.next().ok_or("--policy needs a value")?;
+                policy = Policy::parse(value).ok_or(format!("unknown policy: {}", value))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => filename = Some(arg.clone()),
+        }
+    }
 
-    is_increasing || is_decreasing
+    Ok(Options {
+        filename: filename.ok_or("missing filename")?,
+        rules,
+        policy,
+    })
 }
@@ -1,89 +1,392 @@
 use std::env;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
+mod dampener;
+mod diagnosis;
+#[cfg(test)]
+mod fixtures;
+mod monitor;
+mod parallel;
+mod policy;
+mod repair;
+#[cfg(test)]
+#[path = "../common/rng.rs"]
+mod rng;
+mod rules;
+mod segments;
+mod stats;
+
+use dampener::{is_valid_sequence, min_removals};
+use diagnosis::{ReportFormat, Status};
+use monitor::{MonitorExt, Verdict};
+use policy::{Malformed, Outcome, Policy};
+use repair::EditCosts;
+use rules::SafetyRules;
+use stats::Statistics;
+
+const USAGE: &str = "<filename> [--rules <file>] [--max-removals K] [--report csv|jsonl] \
+[--repair [--edit-costs R,I,D]] [--segments] [--stream] \
+[--policy skip|strict|lenient|spec] [--stats] [--threads N]";
+
+struct Options {
+    filename: String,
+    rules: SafetyRules,
+    // None keeps the single-removal dampener
+    max_removals: Option<usize>,
+    // Per-report diagnosis on stdout, the count and stats then go to stderr
+    report: Option<ReportFormat>,
+    // Print the cheapest edits making each unsafe report safe
+    repair: Option<EditCosts>,
+    // Print the longest and all maximal safe windows of each report
+    segments: bool,
+    // Check level by level without collecting the line first
+    stream: bool,
+    // Handling of lines that aren't a report of at least two levels
+    policy: Policy,
+    // Print step, violation and dampener statistics after the count
+    stats: bool,
+    // Check chunks of the file on this many threads
+    threads: usize,
+}
+
 fn main() -> io::Result<()> {
     // Get command line arguments
     let args: Vec<String> = env::args().collect();
     
     // Check if filename was provided
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
+        eprintln!("Usage: {} {}", args[0], USAGE);
         std::process::exit(1);
     }
 
-    // Get the filename from arguments
-    let filename = &args[1];
-    
+    let options = match parse_options(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: {} {}", args[0], USAGE);
+            std::process::exit(1);
+        }
+    };
     // Read the file line by line
-    let path = Path::new(filename);
+    let path = Path::new(&options.filename);
     let file = File::open(path)?;
     let reader = io::BufReader::new(file);
 
-    let mut valid_lines = 0; 
-
-    // Process each line
-    for line in reader.lines() {
-        let line = line?;
-        let numbers: Result<Vec<i32>, _> = line
-            .split_whitespace()
-            .map(|s| s.parse::<i32>())
-            .collect();
-        
-        match numbers {
-            Ok(nums) => {
-                if nums.len() < 2 {
-                    eprintln!("Error: Each line must contain at least 2 numbers");
-                    continue;
-                }
-            
-                if is_valid_sequence(&nums) {
-                    valid_lines += 1;
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    if let Some(format) = options.report {
+        diagnosis::write_header(&mut out, format)?;
+    }
+
+    let tally = check_reports(reader, &mut out, &options, parallel::CHUNK_SIZE)?;
+
+    out.flush()?;
+    if let Some((line_number, problem)) = tally.abort {
+        eprintln!("Error: line {}, column {}: {}", line_number, problem.column(), problem);
+        std::process::exit(1);
+    }
+    if !tally.malformed.is_empty() {
+        eprintln!("{} malformed lines:", tally.malformed.len());
+        for (line_number, problem, outcome) in &tally.malformed {
+            eprintln!("  line {}, column {}: {} ({})", line_number, problem.column(), problem, outcome.name());
+        }
+    }
+
+    if options.report.is_some() {
+        eprintln!("Number of valid sequences: {}", tally.valid);
+    } else {
+        writeln!(out, "Number of valid sequences: {}", tally.valid)?;
+    }
+    // Like the count, kept off stdout when that holds the per-report output
+    if options.stats {
+        if options.report.is_some() {
+            tally.statistics.write(&mut io::stderr())?;
+        } else {
+            tally.statistics.write(&mut out)?;
+        }
+    }
+    Ok(())
+}
+
+// Check every line of the input, on several threads in chunks of about
+// `chunk_size` bytes when asked to
+fn check_reports<R: BufRead, W: Write>(reader: R, out: &mut W, options: &Options, chunk_size: u64) -> io::Result<Tally> {
+    let mut tally = Tally::default();
+    if options.threads > 1 {
+        // Each chunk writes its output to a buffer, copied out in input order
+        let check_chunk = |first_line: usize, text: &str| -> io::Result<(Vec<u8>, Tally)> {
+            let mut buffer = Vec::new();
+            let mut chunk = Tally::default();
+            for (idx, line) in text.lines().enumerate() {
+                check_line(&mut buffer, first_line + idx, line, options, &mut chunk)?;
+                if chunk.abort.is_some() {
+                    break;
                 }
             }
-            Err(e) => {
-                eprintln!("Error parsing line: {}", e);
-                continue;
+            Ok((buffer, chunk))
+        };
+        parallel::for_each_chunk(reader, options.threads, chunk_size, check_chunk, |result| {
+            let (buffer, chunk) = result?;
+            out.write_all(&buffer)?;
+            tally.merge(chunk);
+            Ok(tally.abort.is_none())
+        })?;
+    } else {
+        // Process each line
+        for (idx, line) in reader.lines().enumerate() {
+            check_line(out, idx + 1, &line?, options, &mut tally)?;
+            if tally.abort.is_some() {
+                break;
             }
         }
     }
+    Ok(tally)
+}
 
-    println!("Number of valid sequences: {}", valid_lines);
-    Ok(())
+// What the reports add up to, kept per chunk when running on several
+// threads and merged in input order
+#[derive(Default)]
+struct Tally {
+    valid: usize,
+    statistics: Statistics,
+    // Lines that weren't a proper report, for the summary at the end
+    malformed: Vec<(usize, Malformed, Outcome)>,
+    // The line that stops the run under the strict policy
+    abort: Option<(usize, Malformed)>,
 }
 
-fn is_valid_sequence(nums: &[i32]) -> bool {
-    // First check if the sequence is valid without removing any element
-    if is_valid_without_removal(nums) {
-        return true;
+impl Tally {
+    fn merge(&mut self, other: Tally) {
+        self.valid += other.valid;
+        self.statistics.merge(other.statistics);
+        self.malformed.extend(other.malformed);
+        if self.abort.is_none() {
+            self.abort = other.abort;
+        }
+    }
+}
+
+// Check one line, writing any per-report output for it
+fn check_line<W: Write>(out: &mut W, line_number: usize, line: &str, options: &Options, tally: &mut Tally) -> io::Result<()> {
+    let rules = &options.rules;
+    let parsed = if options.stream {
+        match stream_line(line, rules, options.max_removals != Some(0)) {
+            Ok(Verdict::Safe) => {
+                tally.valid += 1;
+                return Ok(());
+            }
+            Ok(Verdict::Unsafe) => return Ok(()),
+            Ok(Verdict::Unknown) => Err(policy::too_short(line, line.split_whitespace().count())),
+            Err(problem) => Err(problem),
+        }
+    } else {
+        policy::parse_report(line)
+    };
+
+    let nums = match parsed {
+        Ok(nums) => nums,
+        Err(problem) => {
+            match options.policy.outcome(&problem) {
+                Some(Outcome::CountedSafe) => {
+                    tally.valid += 1;
+                    if options.stats {
+                        tally.statistics.add_single_level();
+                    }
+                }
+                Some(outcome) => tally.malformed.push((line_number, problem, outcome)),
+                None => tally.abort = Some((line_number, problem)),
+            }
+            return Ok(());
+        }
+    };
+
+    if options.segments {
+        print_segments(out, line_number, &nums, rules)?;
+    }
+    if let Some(costs) = options.repair {
+        print_repair(out, line_number, &nums, rules, costs)?;
     }
 
+    let valid = match options.report {
+        Some(format) => {
+            let diagnosis = diagnosis::diagnose(line_number, &nums, rules, options.max_removals);
+            diagnosis::write_diagnosis(out, format, &diagnosis)?;
+            diagnosis.status != Status::Unsafe
+        }
+        None => match options.max_removals {
+            Some(k) => min_removals(&nums, rules) <= k,
+            None => is_valid_sequence(&nums, rules),
+        },
+    };
+    if valid {
+        tally.valid += 1;
+    }
+    if options.stats {
+        tally.statistics.add(&nums, rules, options.max_removals);
+    }
+    Ok(())
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut filename = None;
+    let mut rules = SafetyRules::default();
+    let mut max_removals = None;
+    let mut report = None;
+    let mut repair = false;
+    let mut segments = false;
+    let mut stream = false;
+    let mut policy = Policy::Skip;
+    let mut stats = false;
+    let mut threads = 1;
+    let mut costs = EditCosts::default();
 
-    for skip_idx in 0..nums.len() {
-        let mut temp_nums: Vec<i32> = Vec::new();
-        for (i, &num) in nums.iter().enumerate() {
-            if i != skip_idx {
-                temp_nums.push(num);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Step limits and direction come from the rules file when one is given
+            "--rules" => {
+                let path = iter.next().ok_or("--rules needs a file")?;
+                rules = SafetyRules::load(path).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "--max-removals" => {
+                let value = iter.next().ok_or("--max-removals needs a value")?;
+                max_removals = Some(value.parse().map_err(|_| format!("invalid removal count: {}", value))?);
+            }
+            "--report" => {
+                let value = iter.next().ok_or("--report needs a format")?;
+                report = Some(ReportFormat::parse(value).ok_or(format!("unknown report format: {}", value))?);
             }
+            "--repair" => repair = true,
+            "--segments" => segments = true,
+            "--stream" => stream = true,
+            "--stats" => stats = true,
+            "--threads" => {
+                let value = iter.next().ok_or("--threads needs a value")?;
+                threads = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid thread count: {}", value)),
+                };
+            }
+            "--policy" => {
+                let value = iter.next().ok_or("--policy needs a value")?;
+                policy = Policy::parse(value).ok_or(format!("unknown policy: {}", value))?;
+            }
+            "--edit-costs" => {
+                let value = iter.next().ok_or("--edit-costs needs R,I,D")?;
+                costs = EditCosts::parse(value)
+                    .ok_or(format!("invalid edit costs: {} (each at most {})", value, repair::MAX_COST))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => filename = Some(arg.clone()),
         }
-        if is_valid_without_removal(&temp_nums) {
-            return true;
+    }
+
+    if stream {
+        if report.is_some() || repair || segments || stats {
+            return Err("--stream only counts, it can't be combined with per-report output".to_string());
+        }
+        if max_removals.is_some_and(|k| k > 1) {
+            return Err("--stream supports at most one removal".to_string());
         }
     }
-    false
+
+    Ok(Options {
+        filename: filename.ok_or("missing filename")?,
+        rules,
+        max_removals,
+        report,
+        repair: if repair { Some(costs) } else { None },
+        segments,
+        stream,
+        policy,
+        stats,
+        threads,
+    })
 }
 
-fn is_valid_without_removal(nums: &[i32]) -> bool {
+// Feed the levels of a line through a SafetyMonitor, stopping at the first
+// level that doesn't parse. Levels after the report turned unsafe are still
+// parsed, so a bad one is reported the same as without --stream.
+fn stream_line(line: &str, rules: &SafetyRules, dampener: bool) -> Result<Verdict, Malformed> {
+    let mut problem = None;
+    let levels = policy::tokens(line)
+        .map_while(|(column, token)| policy::parse_level(column, token).map_err(|e| problem = Some(e)).ok());
+    let mut verdict = Verdict::Unknown;
+    for (_, after) in levels.monitor_safety(rules, dampener) {
+        verdict = after;
+    }
+    match problem {
+        Some(problem) => Err(problem),
+        None => Ok(verdict),
+    }
+}
+
+fn print_segments<W: Write>(out: &mut W, line: usize, nums: &[i32], rules: &SafetyRules) -> io::Result<()> {
+    let found = segments::safe_segments(nums, rules);
+    let longest = match segments::longest(&found) {
+        Some(longest) => longest,
+        None => return writeln!(out, "Line {}: no safe segment", line),
+    };
+    let all = found.iter()
+        .map(|s| format!("{}..={} {}", s.start, s.end, diagnosis::direction_name(s.direction)))
+        .collect::<Vec<String>>()
+        .join(", ");
+    writeln!(
+        out,
+        "Line {}: longest {}..={} {} ({} levels); maximal: {}",
+        line, longest.start, longest.end, diagnosis::direction_name(longest.direction), longest.len(), all
+    )
+}
+
+fn print_repair<W: Write>(out: &mut W, line: usize, nums: &[i32], rules: &SafetyRules, costs: EditCosts) -> io::Result<()> {
+    if rules::is_valid_without_removal(nums, rules) {
+        return Ok(());
+    }
+    let join = |values: &[i32]| values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(" ");
+
+    match repair::repair(nums, rules, costs) {
+        Ok(found) => {
+            let edits = found.edits.iter().map(|e| e.to_string()).collect::<Vec<String>>().join(", ");
+            writeln!(out, "Line {}: {} -> {} (cost {}: {})", line, join(nums), join(&found.repaired), found.cost, edits)
+        }
+        Err(reason) => writeln!(out, "Line {}: {} has no repair: {}", line, join(nums), reason),
+    }
+}
 
-    let is_increasing = (0..nums.len()-1).all(|i| {
-        let diff = nums[i+1] - nums[i];
-        diff >= 1 && diff <= 3
-    });
-    let is_decreasing = (0..nums.len()-1).all(|i| {
-        let diff = nums[i] - nums[i+1];
-        diff >= 1 && diff <= 3
-    });
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    is_increasing || is_decreasing
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_options(&args).expect("valid options")
+    }
+
+    // Per-report output, count and malformed lines of one run
+    fn run(text: &str, options: &Options, chunk_size: u64) -> (String, usize, Vec<(usize, usize)>) {
+        let mut out = Vec::new();
+        let tally = check_reports(text.as_bytes(), &mut out, options, chunk_size).expect("reading from memory");
+        let malformed = tally.malformed.iter().map(|(line, problem, _)| (*line, problem.column())).collect();
+        (String::from_utf8(out).expect("UTF-8 output"), tally.valid, malformed)
+    }
+
+    // Chunks of a few bytes cut through most lines, and the last line has
+    // no newline, yet every thread count has to match the sequential run
+    #[test]
+    fn threads_match_sequential_run() {
+        let text = "7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n5\n1 3 2 4 5\n8 6 4 4 1\nx 1\n1 3 6 7 9";
+        let sequential = options(&["q.txt", "--report", "csv", "--segments", "--policy", "lenient"]);
+        let expected = run(text, &sequential, parallel::CHUNK_SIZE);
+        assert_eq!(expected.1, 4);
+        assert_eq!(expected.2, [(4, 2), (7, 1)]);
+
+        for threads in ["2", "3", "8"] {
+            let parallel = options(&["q.txt", "--report", "csv", "--segments", "--policy", "lenient", "--threads", threads]);
+            for chunk_size in 1..16 {
+                assert_eq!(run(text, &parallel, chunk_size), expected, "{} threads, chunks of {} bytes", threads, chunk_size);
+            }
+        }
+    }
 }
@@ -0,0 +1,187 @@
+use crate::rules::{is_valid_without_removal, SafetyRules};
+
+// The Problem Dampener: a report is also safe when removing a single level
+// makes it safe.
+
+/// Linear time and no allocation.
+pub fn is_valid_sequence(nums: &[i32], rules: &SafetyRules) -> bool {
+    is_valid_without_removal(nums, rules) || dampened_removal(nums, rules).is_some()
+}
+
+/// Index of a level whose removal leaves a safe report, tried once per
+/// direction the rules allow
+pub fn dampened_removal(nums: &[i32], rules: &SafetyRules) -> Option<usize> {
+    rules.directions().iter().find_map(|&sign| removal_for_direction(nums, rules, sign))
+}
+
+// Removing level k drops the steps (k-1, k) and (k, k+1) and adds the
+// bridging step (k-1, k+1). So every bad step has to be one of the two
+// dropped ones, which leaves at most two candidates between the last and
+// the first bad step.
+fn removal_for_direction(nums: &[i32], rules: &SafetyRules, sign: i64) -> Option<usize> {
+    let n = nums.len();
+    let step_ok = |i: usize, j: usize| rules.step_allowed(nums[j] as i64 - nums[i] as i64, sign);
+
+    let mut first_bad = None;
+    let mut last_bad = None;
+    for i in 0..n.saturating_sub(1) {
+        if !step_ok(i, i + 1) {
+            first_bad.get_or_insert(i);
+            last_bad = Some(i);
+        }
+    }
+
+    let extremes = Extremes::of(nums);
+    let lo = last_bad.unwrap_or(0);
+    let hi = first_bad.map_or(n, |first| first + 2).min(n);
+    (lo..hi).find(|&k| {
+        (k == 0 || k + 1 == n || step_ok(k - 1, k + 1))
+            && rules.span_allowed(extremes.lowest(k), extremes.highest(k))
+    })
+}
+
+// The two lowest and two highest levels, so the span without any one level
+// is known without another pass
+struct Extremes<'a> {
+    nums: &'a [i32],
+    lowest: [Option<usize>; 2],
+    highest: [Option<usize>; 2],
+}
+
+impl<'a> Extremes<'a> {
+    fn of(nums: &'a [i32]) -> Self {
+        let mut extremes = Extremes { nums, lowest: [None; 2], highest: [None; 2] };
+        for (i, &num) in nums.iter().enumerate() {
+            match extremes.lowest {
+                [None, _] => extremes.lowest = [Some(i), None],
+                [Some(a), _] if num < nums[a] => extremes.lowest = [Some(i), Some(a)],
+                [a, None] => extremes.lowest = [a, Some(i)],
+                [a, Some(b)] if num < nums[b] => extremes.lowest = [a, Some(i)],
+                _ => {}
+            }
+            match extremes.highest {
+                [None, _] => extremes.highest = [Some(i), None],
+                [Some(a), _] if num > nums[a] => extremes.highest = [Some(i), Some(a)],
+                [a, None] => extremes.highest = [a, Some(i)],
+                [a, Some(b)] if num > nums[b] => extremes.highest = [a, Some(i)],
+                _ => {}
+            }
+        }
+        extremes
+    }
+
+    fn pick(&self, pair: [Option<usize>; 2], removed: usize) -> Option<&'a i32> {
+        let idx = if pair[0] == Some(removed) { pair[1] } else { pair[0] };
+        idx.map(|i| &self.nums[i])
+    }
+
+    fn lowest(&self, removed: usize) -> Option<&'a i32> {
+        self.pick(self.lowest, removed)
+    }
+
+    fn highest(&self, removed: usize) -> Option<&'a i32> {
+        self.pick(self.highest, removed)
+    }
+}
+
+/// Fewest levels to remove for the report to be safe, the length of the
+/// report minus its longest safe subsequence. A dampener allowing k
+/// removals accepts the report when this is at most k.
+pub fn min_removals(nums: &[i32], rules: &SafetyRules) -> usize {
+    let longest = rules.directions().iter()
+        .map(|&sign| match rules.max_span {
+            // The kept levels must fit in a window [lo, lo + max_span] that
+            // can be assumed to start at one of the levels
+            Some(max_span) => nums.iter()
+                .map(|&lo| longest_safe_subsequence(nums, rules, sign, lo as i64, lo as i64 + max_span as i64))
+                .max()
+                .unwrap_or(0),
+            None => longest_safe_subsequence(nums, rules, sign, i64::MIN, i64::MAX),
+        })
+        .max()
+        .unwrap_or(0);
+    nums.len() - longest
+}
+
+// O(n^2) DP over the levels inside [lo, hi]: longest[j] is the longest safe
+// subsequence ending at level j
+fn longest_safe_subsequence(nums: &[i32], rules: &SafetyRules, sign: i64, lo: i64, hi: i64) -> usize {
+    let inside = |num: i32| (lo..=hi).contains(&(num as i64));
+    let mut longest = vec![0; nums.len()];
+    let mut best = 0;
+
+    for j in 0..nums.len() {
+        if !inside(nums[j]) {
+            continue;
+        }
+        longest[j] = 1;
+        for i in 0..j {
+            if longest[i] > 0 && longest[i] + 1 > longest[j]
+                && rules.step_allowed(nums[j] as i64 - nums[i] as i64, sign) {
+                longest[j] = longest[i] + 1;
+            }
+        }
+        best = best.max(longest[j]);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{random_report, random_rules};
+    use crate::rng::XorShift;
+    use crate::diagnosis::first_violation;
+
+    // The original approach: try the report without each level in turn
+    fn is_valid_sequence_brute_force(nums: &[i32], rules: &SafetyRules) -> bool {
+        // First check if the sequence is valid without removing any element
+        if is_valid_without_removal(nums, rules) {
+            return true;
+        }
+
+        for skip_idx in 0..nums.len() {
+            let mut temp_nums: Vec<i32> = Vec::new();
+            for (i, &num) in nums.iter().enumerate() {
+                if i != skip_idx {
+                    temp_nums.push(num);
+                }
+            }
+            if is_valid_without_removal(&temp_nums, rules) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Compare the linear dampener and min_removals with the brute force on
+    // random reports and random rules
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = XorShift(0x2024_1202);
+        for _ in 0..200_000 {
+            let rules = random_rules(&mut rng);
+            let nums = random_report(&mut rng, &rules);
+
+            let expected = is_valid_sequence_brute_force(&nums, &rules);
+            assert_eq!(is_valid_sequence(&nums, &rules), expected, "{:?} under {:?}", nums, rules);
+            assert_eq!(
+                first_violation(&nums, &rules).is_none(),
+                is_valid_without_removal(&nums, &rules),
+                "first_violation of {:?} under {:?}",
+                nums,
+                rules
+            );
+            if let Some(k) = dampened_removal(&nums, &rules) {
+                let mut rest = nums.clone();
+                rest.remove(k);
+                assert!(is_valid_without_removal(&rest, &rules), "removing level {} of {:?} under {:?}", k, nums, rules);
+            }
+
+            // One allowed removal has to give the same answer as the dampener
+            let removals = min_removals(&nums, &rules);
+            assert_eq!(removals <= 1, expected, "{} removals for {:?} under {:?}", removals, nums, rules);
+            assert_eq!(removals == 0, is_valid_without_removal(&nums, &rules), "{:?} under {:?}", nums, rules);
+        }
+    }
+}
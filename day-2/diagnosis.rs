@@ -0,0 +1,176 @@
+use std::io::{self, Write};
+
+use crate::dampener::{dampened_removal, min_removals};
+use crate::rules::{AllowedDirection, SafetyRules, Violation};
+
+// Per-report output so operators can act on single reports instead of the
+// count alone
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Safe,
+    SafeWithDampener,
+    Unsafe,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Safe => "safe",
+            Status::SafeWithDampener => "safe-with-dampener",
+            Status::Unsafe => "unsafe",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ReportFormat {
+    pub fn parse(name: &str) -> Option<ReportFormat> {
+        match name {
+            "csv" => Some(ReportFormat::Csv),
+            "jsonl" => Some(ReportFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+pub struct Diagnosis {
+    // 1-based line in the input file
+    pub line: usize,
+    pub status: Status,
+    // Sign of the first non-zero step, 0 for a flat report
+    pub direction: i64,
+    // Level index where the unmodified report first breaks the rules
+    pub violation: Option<(usize, Violation)>,
+    // Level the dampener removed to make the report safe
+    pub removed: Option<usize>,
+}
+
+/// With `max_removals` the report counts as dampened when that many removals
+/// are enough. `removed` is only filled in for dampened reports that a single
+/// removal makes safe.
+pub fn diagnose(line: usize, nums: &[i32], rules: &SafetyRules, max_removals: Option<usize>) -> Diagnosis {
+    let violation = first_violation(nums, rules);
+    let mut removed = None;
+
+    let status = if violation.is_none() {
+        Status::Safe
+    } else {
+        let single = dampened_removal(nums, rules);
+        let dampened = match max_removals {
+            Some(k) => min_removals(nums, rules) <= k,
+            None => single.is_some(),
+        };
+        if dampened {
+            removed = single;
+            Status::SafeWithDampener
+        } else {
+            Status::Unsafe
+        }
+    };
+
+    Diagnosis {
+        line,
+        status,
+        direction: detected_direction(nums),
+        violation,
+        removed,
+    }
+}
+
+pub fn direction_name(sign: i64) -> &'static str {
+    match sign {
+        1 => "increasing",
+        -1 => "decreasing",
+        _ => "flat",
+    }
+}
+
+pub fn violation_name(violation: Violation) -> &'static str {
+    match violation {
+        Violation::ZeroStep => "zero-step",
+        Violation::StepTooSmall => "step-too-small",
+        Violation::StepTooLarge => "step-too-large",
+        Violation::DirectionChange => "direction-change",
+        Violation::SpanTooLarge => "span-too-large",
+    }
+}
+
+// Sign of the first non-zero step, 0 for a flat report
+pub fn detected_direction(nums: &[i32]) -> i64 {
+    nums.windows(2)
+        .map(|pair| (pair[1] as i64 - pair[0] as i64).signum())
+        .find(|&sign| sign != 0)
+        .unwrap_or(0)
+}
+
+/// Index of the level where the report first breaks the rules and how.
+/// Under `either` the first non-zero step fixes the direction.
+pub fn first_violation(nums: &[i32], rules: &SafetyRules) -> Option<(usize, Violation)> {
+    let mut sign = match rules.direction {
+        AllowedDirection::Increasing => 1,
+        AllowedDirection::Decreasing => -1,
+        AllowedDirection::Either | AllowedDirection::Unrestricted => 0,
+    };
+    let (mut lo, mut hi) = match nums.first() {
+        Some(&first) => (first, first),
+        None => return None,
+    };
+
+    for (i, pair) in nums.windows(2).enumerate() {
+        let diff = pair[1] as i64 - pair[0] as i64;
+        if let Some(violation) = rules.step_violation(diff, sign) {
+            return Some((i + 1, violation));
+        }
+        if sign == 0 && rules.direction == AllowedDirection::Either {
+            sign = diff.signum();
+        }
+        lo = lo.min(pair[1]);
+        hi = hi.max(pair[1]);
+        if let Some(violation) = rules.span_violation(Some(&lo), Some(&hi)) {
+            return Some((i + 1, violation));
+        }
+    }
+    None
+}
+
+pub fn write_header<W: Write>(out: &mut W, format: ReportFormat) -> io::Result<()> {
+    match format {
+        ReportFormat::Csv => writeln!(out, "line,status,direction,violation_index,violation,removed_index"),
+        ReportFormat::JsonLines => Ok(()),
+    }
+}
+
+pub fn write_diagnosis<W: Write>(out: &mut W, format: ReportFormat, diagnosis: &Diagnosis) -> io::Result<()> {
+    let violation_index = diagnosis.violation.map(|(idx, _)| idx.to_string());
+    let violation = diagnosis.violation.map(|(_, kind)| violation_name(kind).to_string());
+    let removed = diagnosis.removed.map(|idx| idx.to_string());
+
+    match format {
+        ReportFormat::Csv => writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            diagnosis.line,
+            diagnosis.status.name(),
+            direction_name(diagnosis.direction),
+            violation_index.unwrap_or_default(),
+            violation.unwrap_or_default(),
+            removed.unwrap_or_default(),
+        ),
+        ReportFormat::JsonLines => writeln!(
+            out,
+            "{{\"line\":{},\"status\":\"{}\",\"direction\":\"{}\",\"violation_index\":{},\"violation\":{},\"removed_index\":{}}}",
+            diagnosis.line,
+            diagnosis.status.name(),
+            direction_name(diagnosis.direction),
+            violation_index.unwrap_or_else(|| "null".to_string()),
+            violation.map_or("null".to_string(), |kind| format!("\"{}\"", kind)),
+            removed.unwrap_or_else(|| "null".to_string()),
+        ),
+    }
+}
@@ -0,0 +1,40 @@
+use crate::rng::XorShift;
+use crate::rules::{AllowedDirection, SafetyRules};
+
+// Random rules and reports for the randomized tests
+
+pub fn random_rules(rng: &mut XorShift) -> SafetyRules {
+    if rng.chance(25) {
+        return SafetyRules::default();
+    }
+    let min_step = 1 + rng.below(3) as i32;
+    SafetyRules {
+        min_step,
+        max_step: min_step + rng.below(3) as i32,
+        allow_equal: rng.chance(30),
+        direction: match rng.below(4) {
+            0 => AllowedDirection::Increasing,
+            1 => AllowedDirection::Decreasing,
+            2 => AllowedDirection::Either,
+            _ => AllowedDirection::Unrestricted,
+        },
+        max_span: if rng.chance(30) { Some(rng.below(15) as i32) } else { None },
+    }
+}
+
+// Mostly reports that follow the rules with a few levels disturbed, so that
+// all the interesting cases near the safe boundary show up
+pub fn random_report(rng: &mut XorShift, rules: &SafetyRules) -> Vec<i32> {
+    let len = 1 + rng.below(9);
+    let sign = if rng.chance(50) { 1 } else { -1 };
+    let mut nums = vec![rng.below(30) as i32];
+    while nums.len() < len {
+        let step = rules.min_step + rng.below((rules.max_step - rules.min_step + 1) as usize) as i32;
+        nums.push(nums[nums.len() - 1] + sign * step);
+    }
+    for _ in 0..rng.below(3) {
+        let idx = rng.below(len);
+        nums[idx] += rng.below(9) as i32 - 4;
+    }
+    nums
+}
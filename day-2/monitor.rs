@@ -0,0 +1,157 @@
+use crate::rules::SafetyRules;
+
+// Streaming safety check for telemetry feeds: levels are pushed one at a
+// time and the verdict for the levels seen so far is known after each one.
+//
+// Once the levels seen so far are unsafe (even after the dampener's removal)
+// no later level can make them safe again, so Unsafe is final.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+    Safe,
+    Unsafe,
+    // Fewer than two levels seen, nothing to judge yet
+    Unknown,
+}
+
+// One way the levels seen so far can still be completed into a safe report
+#[derive(Clone, Copy, PartialEq)]
+struct Track {
+    // Sign the steps have to follow, see SafetyRules::directions
+    sign: i64,
+    // Last level kept, None before the first one
+    last: Option<i64>,
+    // Whether the dampener's removal has been spent
+    removed: bool,
+    lo: i64,
+    hi: i64,
+}
+
+impl Track {
+    // Same direction and last level, no removal used where the other didn't
+    // and a range inside the other's, so whatever follows `other` follows this
+    fn covers(&self, other: &Track) -> bool {
+        self.sign == other.sign
+            && self.last == other.last
+            && (!self.removed || other.removed)
+            && self.lo >= other.lo
+            && self.hi <= other.hi
+    }
+}
+
+pub struct SafetyMonitor {
+    rules: SafetyRules,
+    dampener: bool,
+    tracks: Vec<Track>,
+    // The tracks before the last push, kept to be refilled by the next one
+    spare: Vec<Track>,
+    seen: usize,
+}
+
+impl SafetyMonitor {
+    pub fn new(rules: &SafetyRules, dampener: bool) -> Self {
+        let tracks = rules.directions().iter()
+            .map(|&sign| Track { sign, last: None, removed: false, lo: i64::MAX, hi: i64::MIN })
+            .collect();
+        SafetyMonitor { rules: rules.clone(), dampener, tracks, spare: Vec::new(), seen: 0 }
+    }
+
+    /// Levels are any integer type that converts to i64 without loss, so
+    /// u64 and usize streams have to be converted by the caller.
+    pub fn push<T: Into<i64>>(&mut self, level: T) -> Verdict {
+        let level = level.into();
+        self.seen += 1;
+
+        let mut next = std::mem::take(&mut self.spare);
+        next.clear();
+        for track in &self.tracks {
+            let (lo, hi) = (track.lo.min(level), track.hi.max(level));
+            let step_ok = track.last.is_none_or(|last| self.rules.step_allowed(level - last, track.sign));
+            let span_ok = self.rules.max_span.is_none_or(|span| hi - lo <= span as i64);
+            if step_ok && span_ok {
+                add_track(&mut next, Track { last: Some(level), lo, hi, ..*track });
+            }
+            // The dampener removes this level instead
+            if self.dampener && !track.removed {
+                add_track(&mut next, Track { removed: true, ..*track });
+            }
+        }
+        self.spare = std::mem::replace(&mut self.tracks, next);
+        self.verdict()
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        if self.tracks.is_empty() {
+            Verdict::Unsafe
+        } else if self.seen < 2 {
+            Verdict::Unknown
+        } else {
+            Verdict::Safe
+        }
+    }
+}
+
+// Keep only the tracks no other track covers
+fn add_track(tracks: &mut Vec<Track>, track: Track) {
+    if tracks.iter().any(|t| t.covers(&track)) {
+        return;
+    }
+    tracks.retain(|t| !track.covers(t));
+    tracks.push(track);
+}
+
+/// Iterator adapter yielding every level together with the verdict after it,
+/// for the same level types as SafetyMonitor::push.
+pub struct Monitored<I> {
+    levels: I,
+    monitor: SafetyMonitor,
+}
+
+impl<I> Iterator for Monitored<I>
+where I: Iterator, I::Item: Copy + Into<i64> {
+    type Item = (I::Item, Verdict);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let level = self.levels.next()?;
+        Some((level, self.monitor.push(level)))
+    }
+}
+
+pub trait MonitorExt: Iterator + Sized {
+    fn monitor_safety(self, rules: &SafetyRules, dampener: bool) -> Monitored<Self> {
+        Monitored { levels: self, monitor: SafetyMonitor::new(rules, dampener) }
+    }
+}
+
+impl<I> MonitorExt for I where I: Iterator, I::Item: Copy + Into<i64> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dampener::is_valid_sequence;
+    use crate::fixtures::{random_report, random_rules};
+    use crate::rng::XorShift;
+    use crate::rules::is_valid_without_removal;
+
+    // Compare the verdict after every prefix with the whole-report checks
+    #[test]
+    fn matches_report_checks() {
+        let mut rng = XorShift(0x2024_1205);
+        for _ in 0..50_000 {
+            let rules = random_rules(&mut rng);
+            let nums = random_report(&mut rng, &rules);
+            let dampener = rng.chance(50);
+
+            for (idx, (_, verdict)) in nums.iter().copied().monitor_safety(&rules, dampener).enumerate() {
+                let prefix = &nums[..=idx];
+                let safe = if dampener { is_valid_sequence(prefix, &rules) } else { is_valid_without_removal(prefix, &rules) };
+                let expected = match (safe, prefix.len()) {
+                    (false, _) => Verdict::Unsafe,
+                    (true, 1) => Verdict::Unknown,
+                    (true, _) => Verdict::Safe,
+                };
+                assert_eq!(verdict, expected, "after {:?} under {:?} (dampener {})", prefix, rules, dampener);
+            }
+        }
+    }
+}
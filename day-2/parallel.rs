@@ -0,0 +1,111 @@
+use std::io::{self, BufRead, Read};
+use std::thread;
+
+// Chunked processing of large report files: the input is cut into chunks
+// of whole lines, one batch of chunks is checked on worker threads at a
+// time and the results are handed back in input order.
+
+// Bytes read per chunk before completing the last line
+pub const CHUNK_SIZE: u64 = 4 << 20;
+
+/// Calls `work` on every chunk of about `chunk_size` bytes with the 1-based
+/// number of its first line, then `collect` on the results in input order.
+/// Stops early when `collect` returns false.
+pub fn for_each_chunk<R, T, F, G>(mut reader: R, threads: usize, chunk_size: u64, work: F, mut collect: G) -> io::Result<()>
+where
+    R: BufRead,
+    T: Send,
+    F: Fn(usize, &str) -> T + Sync,
+    G: FnMut(T) -> io::Result<bool>,
+{
+    let mut first_line = 1;
+    loop {
+        let mut batch: Vec<(usize, String)> = Vec::with_capacity(threads);
+        while batch.len() < threads {
+            let chunk = match next_chunk(&mut reader, chunk_size)? {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            let lines = chunk.lines().count();
+            batch.push((first_line, chunk));
+            first_line += lines;
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let work = &work;
+        let results: Vec<T> = thread::scope(|scope| {
+            let handles: Vec<_> = batch.iter()
+                .map(|(first, chunk)| scope.spawn(move || work(*first, chunk)))
+                .collect();
+            handles.into_iter().map(|h| h.join().expect("worker thread panicked")).collect()
+        });
+        for result in results {
+            if !collect(result)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Around `chunk_size` bytes, cut after a newline. None at the end of the input.
+fn next_chunk<R: BufRead>(reader: &mut R, chunk_size: u64) -> io::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    reader.by_ref().take(chunk_size).read_to_end(&mut bytes)?;
+    if bytes.last().is_some_and(|&b| b != b'\n') {
+        reader.read_until(b'\n', &mut bytes)?;
+    }
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every line with its number, in the order the chunks hand them back
+    fn numbered_lines(text: &str, threads: usize, chunk_size: u64) -> Vec<(usize, String)> {
+        let mut found = Vec::new();
+        let work = |first: usize, chunk: &str| -> Vec<(usize, String)> {
+            chunk.lines().enumerate().map(|(idx, line)| (first + idx, line.to_string())).collect()
+        };
+        for_each_chunk(text.as_bytes(), threads, chunk_size, work, |lines| {
+            found.extend(lines);
+            Ok(true)
+        })
+        .expect("reading from memory");
+        found
+    }
+
+    // Chunks a few bytes long cut almost every line, some lines are longer
+    // than a chunk and the last one has no newline
+    #[test]
+    fn matches_sequential_order() {
+        let text = "7 6 4 2 1\n1 2 7 8 9\n\n9 7 6 2 1 1 1 1 1 1 1 1 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9";
+        let expected: Vec<(usize, String)> = text.lines().enumerate().map(|(idx, line)| (idx + 1, line.to_string())).collect();
+        for threads in 1..5 {
+            for chunk_size in 1..12 {
+                assert_eq!(numbered_lines(text, threads, chunk_size), expected, "{} threads, chunks of {} bytes", threads, chunk_size);
+            }
+        }
+        assert_eq!(numbered_lines(&format!("{}\n", text), 3, 5), expected, "with a final newline");
+        assert!(numbered_lines("", 2, 5).is_empty());
+    }
+
+    #[test]
+    fn stops_when_collect_says_so() {
+        let mut seen = 0;
+        let work = |first: usize, _: &str| first;
+        for_each_chunk("1\n2\n3\n4\n5\n".as_bytes(), 2, 2, work, |_| {
+            seen += 1;
+            Ok(seen < 3)
+        })
+        .expect("reading from memory");
+        assert_eq!(seen, 3);
+    }
+}
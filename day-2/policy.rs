@@ -0,0 +1,120 @@
+use std::fmt;
+use std::num::ParseIntError;
+
+// What to do with lines that aren't a report of at least two levels
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Policy {
+    // Leave the line out of the count, the default
+    Skip,
+    // Abort at the first bad line with its line and column
+    Strict,
+    // Count the line as an unsafe report
+    Lenient,
+    // A single level is trivially safe, other bad lines are skipped
+    Spec,
+}
+
+impl Policy {
+    pub fn parse(name: &str) -> Option<Policy> {
+        match name {
+            "skip" => Some(Policy::Skip),
+            "strict" => Some(Policy::Strict),
+            "lenient" => Some(Policy::Lenient),
+            "spec" => Some(Policy::Spec),
+            _ => None,
+        }
+    }
+
+    /// None means the run has to stop
+    pub fn outcome(&self, problem: &Malformed) -> Option<Outcome> {
+        match (self, problem) {
+            (Policy::Skip, _) => Some(Outcome::Skipped),
+            (Policy::Strict, _) => None,
+            (Policy::Lenient, _) => Some(Outcome::CountedUnsafe),
+            (Policy::Spec, Malformed::TooShort { levels: 1, .. }) => Some(Outcome::CountedSafe),
+            (Policy::Spec, _) => Some(Outcome::Skipped),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Skipped,
+    CountedUnsafe,
+    CountedSafe,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Skipped => "skipped",
+            Outcome::CountedUnsafe => "counted as unsafe",
+            Outcome::CountedSafe => "counted as safe",
+        }
+    }
+}
+
+// Columns are 1-based character positions
+#[derive(Debug)]
+pub enum Malformed {
+    Parse { column: usize, token: String, error: ParseIntError },
+    // Column is where the next level was expected
+    TooShort { column: usize, levels: usize },
+}
+
+impl Malformed {
+    pub fn column(&self) -> usize {
+        match self {
+            Malformed::Parse { column, .. } | Malformed::TooShort { column, .. } => *column,
+        }
+    }
+}
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Malformed::Parse { token, error, .. } => write!(f, "can't parse level '{}': {}", token, error),
+            Malformed::TooShort { levels, .. } => write!(f, "expected at least 2 levels, found {}", levels),
+        }
+    }
+}
+
+// Whitespace separated tokens with the column they start at
+pub fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut column = 0;
+    let mut start = None;
+    let mut chars = line.char_indices().chain(std::iter::once((line.len(), ' ')));
+    std::iter::from_fn(move || {
+        for (byte, ch) in chars.by_ref() {
+            column += 1;
+            match (start, ch.is_whitespace()) {
+                (None, false) => start = Some((byte, column)),
+                (Some((begin, begin_column)), true) => {
+                    start = None;
+                    return Some((begin_column, &line[begin..byte]));
+                }
+                _ => {}
+            }
+        }
+        None
+    })
+}
+
+pub fn parse_level(column: usize, token: &str) -> Result<i32, Malformed> {
+    token.parse::<i32>().map_err(|error| Malformed::Parse { column, token: token.to_string(), error })
+}
+
+pub fn too_short(line: &str, levels: usize) -> Malformed {
+    Malformed::TooShort { column: line.chars().count() + 1, levels }
+}
+
+pub fn parse_report(line: &str) -> Result<Vec<i32>, Malformed> {
+    let nums = tokens(line)
+        .map(|(column, token)| parse_level(column, token))
+        .collect::<Result<Vec<i32>, Malformed>>()?;
+    if nums.len() < 2 {
+        return Err(too_short(line, nums.len()));
+    }
+    Ok(nums)
+}
@@ -0,0 +1,310 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use crate::rules::{is_valid_without_removal, SafetyRules};
+
+// Cheapest way to edit an unsafe report into a safe one, where besides
+// removing a level a level can be replaced by another value or a new level
+// inserted.
+//
+// The search runs over states (levels consumed, last value written) with
+// Dijkstra, once per direction the rules allow, and with a span limit once
+// per window of values the repaired report may occupy.
+
+// Largest number of candidate values the search is run over
+pub const MAX_DOMAIN: usize = 1 << 14;
+
+// Largest number of states all the searches together may visit, as one search
+// runs per window and direction
+pub const MAX_WORK: usize = 1 << 24;
+
+// Largest cost a single edit may have, so the cost of a whole repair stays
+// well inside a u32
+pub const MAX_COST: u32 = 1 << 16;
+
+#[derive(Clone, Copy)]
+pub struct EditCosts {
+    pub replace: u32,
+    pub insert: u32,
+    pub delete: u32,
+}
+
+impl Default for EditCosts {
+    fn default() -> Self {
+        EditCosts { replace: 1, insert: 1, delete: 1 }
+    }
+}
+
+impl EditCosts {
+    // "R,I,D" for the replace, insert and delete costs, each at most MAX_COST
+    pub fn parse(spec: &str) -> Option<EditCosts> {
+        let costs: Vec<u32> = spec
+            .split(',')
+            .map(|c| c.trim().parse().ok().filter(|&c| c <= MAX_COST))
+            .collect::<Option<_>>()?;
+        match costs[..] {
+            [replace, insert, delete] => Some(EditCosts { replace, insert, delete }),
+            _ => None,
+        }
+    }
+}
+
+// Indices refer to levels of the original report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Replace { index: usize, from: i32, to: i32 },
+    // New level written before original level `index`
+    Insert { index: usize, value: i32 },
+    Delete { index: usize, value: i32 },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edit::Replace { index, from, to } => write!(f, "replace level {} {} -> {}", index, from, to),
+            Edit::Insert { index, value } => write!(f, "insert {} before level {}", value, index),
+            Edit::Delete { index, value } => write!(f, "delete level {} ({})", index, value),
+        }
+    }
+}
+
+pub struct Repair {
+    pub cost: u32,
+    pub edits: Vec<Edit>,
+    pub repaired: Vec<i32>,
+}
+
+// Why no repair was found
+#[derive(Debug)]
+pub enum Unrepaired {
+    // More candidate values than MAX_DOMAIN
+    DomainTooWide { values: i64 },
+    // More search states over all windows than MAX_WORK
+    TooMuchWork { states: usize },
+    // No window and direction gave a safe report
+    NotFound,
+}
+
+impl fmt::Display for Unrepaired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unrepaired::DomainTooWide { values } => {
+                write!(f, "levels spread over {} values, at most {} can be searched", values, MAX_DOMAIN)
+            }
+            Unrepaired::TooMuchWork { states } => {
+                write!(f, "{} search states, at most {} can be searched", states, MAX_WORK)
+            }
+            Unrepaired::NotFound => write!(f, "no repair found"),
+        }
+    }
+}
+
+/// The cheapest repair, or why the search gave up. The repaired report is
+/// always checked with `is_valid_without_removal`.
+pub fn repair(nums: &[i32], rules: &SafetyRules, costs: EditCosts) -> Result<Repair, Unrepaired> {
+    let (min, max) = match (nums.iter().min(), nums.iter().max()) {
+        (Some(&min), Some(&max)) => (min as i64, max as i64),
+        _ => return Ok(Repair { cost: 0, edits: Vec::new(), repaired: Vec::new() }),
+    };
+
+    // New values never need to go further out than one step per level
+    let pad = rules.max_step as i64 * (nums.len() as i64 + 1);
+    let lowest = min - pad;
+    let highest = max + pad;
+    if highest - lowest + 1 > MAX_DOMAIN as i64 {
+        return Err(Unrepaired::DomainTooWide { values: highest - lowest + 1 });
+    }
+
+    let windows: Vec<(i64, i64)> = match rules.max_span {
+        Some(span) if (span as i64) < highest - lowest => {
+            (lowest..=highest - span as i64).map(|lo| (lo, lo + span as i64)).collect()
+        }
+        _ => vec![(lowest, highest)],
+    };
+    let width = windows[0].1 - windows[0].0 + 1;
+    let work = rules.directions().len() * windows.len() * (nums.len() + 1) * (width as usize + 1);
+    if work > MAX_WORK {
+        return Err(Unrepaired::TooMuchWork { states: work });
+    }
+
+    let mut best: Option<Repair> = None;
+    for &sign in rules.directions() {
+        for &(lo, hi) in &windows {
+            let search = Search { nums, rules, costs, sign, lo, hi };
+            if let Some(found) = search.run() {
+                if best.as_ref().is_none_or(|b| found.cost < b.cost) {
+                    best = Some(found);
+                }
+            }
+        }
+    }
+
+    let best = best.ok_or(Unrepaired::NotFound)?;
+    // The search only writes allowed steps inside a window, anything else
+    // is a bug in it rather than a limit
+    debug_assert!(is_valid_without_removal(&best.repaired, rules), "unsafe repair {:?} of {:?}", best.repaired, nums);
+    if !is_valid_without_removal(&best.repaired, rules) {
+        return Err(Unrepaired::NotFound);
+    }
+    Ok(best)
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    Keep,
+    Replace(i64),
+    Delete,
+    Insert(i64),
+}
+
+struct Search<'a> {
+    nums: &'a [i32],
+    rules: &'a SafetyRules,
+    costs: EditCosts,
+    sign: i64,
+    lo: i64,
+    hi: i64,
+}
+
+impl Search<'_> {
+    fn width(&self) -> usize {
+        (self.hi - self.lo + 1) as usize
+    }
+
+    // Slot 0 of every level is "nothing written yet"
+    fn state(&self, consumed: usize, last: Option<i64>) -> usize {
+        consumed * (self.width() + 1) + last.map_or(0, |v| (v - self.lo + 1) as usize)
+    }
+
+    fn decode(&self, state: usize) -> (usize, Option<i64>) {
+        let slot = state % (self.width() + 1);
+        let last = if slot == 0 { None } else { Some(self.lo + slot as i64 - 1) };
+        (state / (self.width() + 1), last)
+    }
+
+    fn fits(&self, value: i64, last: Option<i64>) -> bool {
+        (self.lo..=self.hi).contains(&value)
+            && last.is_none_or(|v| self.rules.step_allowed(value - v, self.sign))
+    }
+
+    // Values that may be written after `last`
+    fn next_values(&self, last: Option<i64>) -> Vec<i64> {
+        let (min, max) = (self.rules.min_step as i64, self.rules.max_step as i64);
+        let candidates: Vec<i64> = match last {
+            None => (self.lo..=self.hi).collect(),
+            Some(v) => (min..=max).flat_map(|s| [v + s, v - s]).chain([v]).collect(),
+        };
+        candidates.into_iter().filter(|&w| self.fits(w, last)).collect()
+    }
+
+    fn run(&self) -> Option<Repair> {
+        let n = self.nums.len();
+        let states = (n + 1) * (self.width() + 1);
+        let mut dist = vec![u32::MAX; states];
+        let mut prev: Vec<Option<(usize, Step)>> = vec![None; states];
+        let mut heap = BinaryHeap::new();
+
+        let start = self.state(0, None);
+        dist[start] = 0;
+        heap.push(Reverse((0, start)));
+
+        while let Some(Reverse((cost, state))) = heap.pop() {
+            if cost > dist[state] {
+                continue;
+            }
+            let (consumed, last) = self.decode(state);
+            if consumed == n && last.is_some() {
+                return Some(self.rebuild(state, cost, &prev));
+            }
+
+            let mut moves: Vec<(usize, u32, Step)> = Vec::new();
+            for w in self.next_values(last) {
+                moves.push((self.state(consumed, Some(w)), self.costs.insert, Step::Insert(w)));
+                if consumed < n {
+                    let next = self.state(consumed + 1, Some(w));
+                    if w == self.nums[consumed] as i64 {
+                        moves.push((next, 0, Step::Keep));
+                    } else {
+                        moves.push((next, self.costs.replace, Step::Replace(w)));
+                    }
+                }
+            }
+            if consumed < n {
+                moves.push((self.state(consumed + 1, last), self.costs.delete, Step::Delete));
+            }
+
+            for (next, step_cost, step) in moves {
+                let next_cost = cost.saturating_add(step_cost);
+                if next_cost < dist[next] {
+                    dist[next] = next_cost;
+                    prev[next] = Some((state, step));
+                    heap.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+        None
+    }
+
+    fn rebuild(&self, goal: usize, cost: u32, prev: &[Option<(usize, Step)>]) -> Repair {
+        let mut steps = Vec::new();
+        let mut state = goal;
+        while let Some((before, step)) = prev[state] {
+            steps.push(step);
+            state = before;
+        }
+        steps.reverse();
+
+        let mut edits = Vec::new();
+        let mut repaired = Vec::new();
+        let mut index = 0;
+        for step in steps {
+            match step {
+                Step::Keep => {
+                    repaired.push(self.nums[index]);
+                    index += 1;
+                }
+                Step::Replace(to) => {
+                    edits.push(Edit::Replace { index, from: self.nums[index], to: to as i32 });
+                    repaired.push(to as i32);
+                    index += 1;
+                }
+                Step::Delete => {
+                    edits.push(Edit::Delete { index, value: self.nums[index] });
+                    index += 1;
+                }
+                Step::Insert(value) => {
+                    edits.push(Edit::Insert { index, value: value as i32 });
+                    repaired.push(value as i32);
+                }
+            }
+        }
+        Repair { cost, edits, repaired }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dampener::min_removals;
+    use crate::fixtures::{random_report, random_rules};
+    use crate::rng::XorShift;
+
+    // Repairs of random reports have to be safe and no worse than the
+    // deletion-only answer
+    #[test]
+    fn no_worse_than_removals() {
+        let mut rng = XorShift(0x2024_1203);
+        let costs = EditCosts::default();
+        for _ in 0..5_000 {
+            let rules = random_rules(&mut rng);
+            let nums = random_report(&mut rng, &rules);
+
+            let found = repair(&nums, &rules, costs).unwrap_or_else(|e| panic!("no repair for {:?} under {:?}: {}", nums, rules, e));
+            // Deleting levels is one of the allowed edits, so never worse than that
+            let removals = min_removals(&nums, &rules) as u32;
+            assert_eq!(found.cost == 0, is_valid_without_removal(&nums, &rules), "{:?} under {:?}", nums, rules);
+            assert!(found.cost <= removals, "repair of {:?} under {:?} costs {} with {} removals needed", nums, rules, found.cost, removals);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Which way the levels of a safe report may move
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AllowedDirection {
+    Increasing,
+    Decreasing,
+    // Either way, but the same way across the whole report
+    Either,
+    // No monotonicity requirement, only the step sizes are checked
+    Unrestricted,
+}
+
+// Why a report stops being safe at some level
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Violation {
+    ZeroStep,
+    StepTooSmall,
+    StepTooLarge,
+    // A step against the direction the report took or the rules require
+    DirectionChange,
+    SpanTooLarge,
+}
+
+// Tolerances of a reactor, the defaults are the puzzle's rules
+#[derive(Clone, Debug)]
+pub struct SafetyRules {
+    // Bounds on the size of a non-zero step between adjacent levels
+    pub min_step: i32,
+    pub max_step: i32,
+    // Whether two adjacent levels may be equal
+    pub allow_equal: bool,
+    pub direction: AllowedDirection,
+    // Largest allowed difference between the highest and lowest level
+    pub max_span: Option<i32>,
+}
+
+impl Default for SafetyRules {
+    fn default() -> Self {
+        SafetyRules {
+            min_step: 1,
+            max_step: 3,
+            allow_equal: false,
+            direction: AllowedDirection::Either,
+            max_span: None,
+        }
+    }
+}
+
+impl SafetyRules {
+    /// Reads `key = value` lines, `#` starts a comment. Keys left out keep
+    /// their default:
+    ///
+    ///     min_step = 1
+    ///     max_step = 3
+    ///     allow_equal = false
+    ///     direction = either   # inc, dec, either or none
+    ///     max_span = none      # or a number
+    pub fn load<P>(path: P) -> io::Result<SafetyRules>
+    where P: AsRef<Path> {
+        let text = fs::read_to_string(path)?;
+        SafetyRules::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(text: &str) -> Result<SafetyRules, String> {
+        let mut rules = SafetyRules::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("line {}: expected key = value", idx + 1)),
+            };
+            let invalid = || format!("line {}: invalid value for {}: {}", idx + 1, key, value);
+
+            match key {
+                "min_step" => rules.min_step = value.parse().map_err(|_| invalid())?,
+                "max_step" => rules.max_step = value.parse().map_err(|_| invalid())?,
+                "allow_equal" => rules.allow_equal = value.parse().map_err(|_| invalid())?,
+                "direction" => {
+                    rules.direction = match value {
+                        "inc" => AllowedDirection::Increasing,
+                        "dec" => AllowedDirection::Decreasing,
+                        "either" => AllowedDirection::Either,
+                        "none" => AllowedDirection::Unrestricted,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "max_span" => {
+                    rules.max_span = match value {
+                        "none" => None,
+                        _ => Some(value.parse().map_err(|_| invalid())?),
+                    }
+                }
+                _ => return Err(format!("line {}: unknown setting {}", idx + 1, key)),
+            }
+        }
+
+        if rules.min_step < 1 || rules.min_step > rules.max_step {
+            return Err(format!("steps must satisfy 1 <= min_step <= max_step, got {}..={}", rules.min_step, rules.max_step));
+        }
+        if rules.max_span.is_some_and(|span| span < 0) {
+            return Err("max_span can't be negative".to_string());
+        }
+        Ok(rules)
+    }
+
+    // The fixed directions a safe report may follow, as the sign of its
+    // non-zero steps (0 when any sign is fine)
+    pub fn directions(&self) -> &'static [i64] {
+        match self.direction {
+            AllowedDirection::Increasing => &[1],
+            AllowedDirection::Decreasing => &[-1],
+            AllowedDirection::Either => &[1, -1],
+            AllowedDirection::Unrestricted => &[0],
+        }
+    }
+
+    // What is wrong with a step of `diff` between adjacent levels in a
+    // report following direction `sign`, if anything
+    pub fn step_violation(&self, diff: i64, sign: i64) -> Option<Violation> {
+        if diff == 0 {
+            return if self.allow_equal { None } else { Some(Violation::ZeroStep) };
+        }
+        if diff.abs() > self.max_step as i64 {
+            Some(Violation::StepTooLarge)
+        } else if diff.abs() < self.min_step as i64 {
+            Some(Violation::StepTooSmall)
+        } else if sign != 0 && diff.signum() != sign {
+            Some(Violation::DirectionChange)
+        } else {
+            None
+        }
+    }
+
+    pub fn step_allowed(&self, diff: i64, sign: i64) -> bool {
+        self.step_violation(diff, sign).is_none()
+    }
+
+    // Whether levels between `lowest` and `highest` are too far apart
+    pub fn span_violation(&self, lowest: Option<&i32>, highest: Option<&i32>) -> Option<Violation> {
+        match (self.max_span, lowest, highest) {
+            (Some(max_span), Some(&lo), Some(&hi)) if hi as i64 - lo as i64 > max_span as i64 => {
+                Some(Violation::SpanTooLarge)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn span_allowed(&self, lowest: Option<&i32>, highest: Option<&i32>) -> bool {
+        self.span_violation(lowest, highest).is_none()
+    }
+}
+
+pub fn is_valid_without_removal(nums: &[i32], rules: &SafetyRules) -> bool {
+    rules.directions().iter().any(|&sign| {
+        nums.windows(2).all(|pair| rules.step_allowed(pair[1] as i64 - pair[0] as i64, sign))
+    }) && rules.span_allowed(nums.iter().min(), nums.iter().max())
+}
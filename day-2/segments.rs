@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+
+use crate::diagnosis::detected_direction;
+use crate::rules::SafetyRules;
+
+// Contiguous windows of a report that are safe on their own, to show the
+// structure hidden inside long unsafe reports
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub start: usize,
+    // Inclusive
+    pub end: usize,
+    // Sign of the first non-zero step inside the window, 0 when flat
+    pub direction: i64,
+}
+
+impl Segment {
+    pub fn len(&self) -> usize {
+        self.end - self.start + 1
+    }
+}
+
+/// Every maximal safe window, ordered by start. Linear time: one sliding
+/// window per direction the rules allow, then a merge of the results.
+pub fn safe_segments(nums: &[i32], rules: &SafetyRules) -> Vec<Segment> {
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for &sign in rules.directions() {
+        merged = merge(&merged, &maximal_windows(nums, rules, sign));
+    }
+
+    // After sorting by start and then longest first, a window is contained
+    // in another one exactly when an earlier window reaches at least as far
+    let mut segments: Vec<Segment> = Vec::new();
+    for (start, end) in merged {
+        if segments.last().is_none_or(|s| end > s.end) {
+            segments.push(Segment { start, end, direction: detected_direction(&nums[start..=end]) });
+        }
+    }
+    segments
+}
+
+// Longest window first, the earliest one on ties
+pub fn longest(segments: &[Segment]) -> Option<Segment> {
+    segments.iter().copied().max_by(|a, b| a.len().cmp(&b.len()).then(b.start.cmp(&a.start)))
+}
+
+// For every end r the smallest start l(r) keeping [l, r] safe only moves
+// right, so [l(r), r] is maximal when l(r + 1) moved past l(r)
+fn maximal_windows(nums: &[i32], rules: &SafetyRules, sign: i64) -> Vec<(usize, usize)> {
+    let n = nums.len();
+    let mut windows = Vec::new();
+    // Indices with increasing values / decreasing values, for the span
+    let mut lows: VecDeque<usize> = VecDeque::new();
+    let mut highs: VecDeque<usize> = VecDeque::new();
+    let mut start = 0;
+
+    for end in 0..n {
+        if end > 0 && !rules.step_allowed(nums[end] as i64 - nums[end - 1] as i64, sign) {
+            windows.push((start, end - 1));
+            start = end;
+            lows.clear();
+            highs.clear();
+        }
+
+        while lows.back().is_some_and(|&i| nums[i] >= nums[end]) {
+            lows.pop_back();
+        }
+        lows.push_back(end);
+        while highs.back().is_some_and(|&i| nums[i] <= nums[end]) {
+            highs.pop_back();
+        }
+        highs.push_back(end);
+
+        let mut moved = false;
+        while !rules.span_allowed(lows.front().map(|&i| &nums[i]), highs.front().map(|&i| &nums[i])) {
+            if !moved {
+                windows.push((start, end - 1));
+                moved = true;
+            }
+            start += 1;
+            while lows.front().is_some_and(|&i| i < start) {
+                lows.pop_front();
+            }
+            while highs.front().is_some_and(|&i| i < start) {
+                highs.pop_front();
+            }
+        }
+    }
+    if n > 0 {
+        windows.push((start, n - 1));
+    }
+    windows
+}
+
+// Merge two lists sorted by start (and by end) into one sorted by start,
+// longer windows first on equal starts
+fn merge(a: &[(usize, usize)], b: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let take_a = j == b.len()
+            || (i < a.len() && (a[i].0 < b[j].0 || (a[i].0 == b[j].0 && a[i].1 >= b[j].1)));
+        if take_a {
+            merged.push(a[i]);
+            i += 1;
+        } else {
+            merged.push(b[j]);
+            j += 1;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{random_report, random_rules};
+    use crate::rng::XorShift;
+    use crate::rules::is_valid_without_removal;
+
+    // Compare with every window checked by is_valid_without_removal
+    #[test]
+    fn matches_every_window() {
+        let mut rng = XorShift(0x2024_1204);
+        for _ in 0..50_000 {
+            let rules = random_rules(&mut rng);
+            let nums = random_report(&mut rng, &rules);
+            let n = nums.len();
+
+            let safe = |s: usize, e: usize| is_valid_without_removal(&nums[s..=e], &rules);
+            let expected: Vec<(usize, usize)> = (0..n)
+                .flat_map(|s| (s..n).map(move |e| (s, e)))
+                .filter(|&(s, e)| safe(s, e) && (s == 0 || !safe(s - 1, e)) && (e + 1 == n || !safe(s, e + 1)))
+                .collect();
+            let found: Vec<(usize, usize)> = safe_segments(&nums, &rules).iter().map(|s| (s.start, s.end)).collect();
+            assert_eq!(found, expected, "segments of {:?} under {:?}", nums, rules);
+        }
+    }
+}
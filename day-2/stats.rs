@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::dampener::{dampened_removal, min_removals};
+use crate::diagnosis::{first_violation, violation_name};
+use crate::rules::{SafetyRules, Violation};
+
+// Aggregate view over all reports. Every step goes through
+// SafetyRules::step_violation like is_valid_without_removal does, so the
+// totals here add up to the same answer as the count.
+
+const VIOLATIONS: [Violation; 5] = [
+    Violation::ZeroStep,
+    Violation::StepTooSmall,
+    Violation::StepTooLarge,
+    Violation::DirectionChange,
+    Violation::SpanTooLarge,
+];
+
+#[derive(Default)]
+pub struct Statistics {
+    pub reports: usize,
+    // Safe as they are
+    pub safe: usize,
+    // Safe once the dampener removes a level, or up to --max-removals levels
+    pub dampened: usize,
+    // Signed difference between adjacent levels -> occurrences
+    pub steps: BTreeMap<i64, usize>,
+    // Kind of the first violation of each unsafe report
+    pub violations: BTreeMap<&'static str, usize>,
+    // Level index of the first violation -> reports
+    pub first_positions: BTreeMap<usize, usize>,
+    // Level index the dampener removed -> reports it made safe
+    pub rescued_by: BTreeMap<usize, usize>,
+}
+
+impl Statistics {
+    /// With `max_removals` a report counts as dampened when that many
+    /// removals are enough, like in the count. It is only rescued by a
+    /// level when a single removal is.
+    pub fn add(&mut self, nums: &[i32], rules: &SafetyRules, max_removals: Option<usize>) {
+        self.reports += 1;
+        for pair in nums.windows(2) {
+            *self.steps.entry(pair[1] as i64 - pair[0] as i64).or_insert(0) += 1;
+        }
+
+        let (position, violation) = match first_violation(nums, rules) {
+            Some(found) => found,
+            None => {
+                self.safe += 1;
+                return;
+            }
+        };
+        *self.violations.entry(violation_name(violation)).or_insert(0) += 1;
+        *self.first_positions.entry(position).or_insert(0) += 1;
+
+        let removed = dampened_removal(nums, rules);
+        let dampened = match max_removals {
+            Some(k) => min_removals(nums, rules) <= k,
+            None => removed.is_some(),
+        };
+        if !dampened {
+            return;
+        }
+        self.dampened += 1;
+        if let Some(removed) = removed {
+            *self.rescued_by.entry(removed).or_insert(0) += 1;
+        }
+    }
+
+    /// A single level, which --policy spec counts as a safe report
+    pub fn add_single_level(&mut self) {
+        self.reports += 1;
+        self.safe += 1;
+    }
+
+    pub fn merge(&mut self, other: Statistics) {
+        self.reports += other.reports;
+        self.safe += other.safe;
+        self.dampened += other.dampened;
+        add_counts(&mut self.steps, other.steps);
+        add_counts(&mut self.violations, other.violations);
+        add_counts(&mut self.first_positions, other.first_positions);
+        add_counts(&mut self.rescued_by, other.rescued_by);
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let unsafe_reports = self.reports - self.safe;
+        writeln!(out, "Reports: {}", self.reports)?;
+        writeln!(out, "  safe: {}", self.safe)?;
+        writeln!(out, "  safe with dampener: {}", self.dampened)?;
+        writeln!(out, "  unsafe: {}", unsafe_reports - self.dampened)?;
+
+        writeln!(out, "Step sizes:")?;
+        write_histogram(out, &self.steps)?;
+
+        writeln!(out, "First violations by kind:")?;
+        for kind in VIOLATIONS.iter() {
+            let count = self.violations.get(violation_name(*kind)).copied().unwrap_or(0);
+            writeln!(out, "  {:>16}: {} ({})", violation_name(*kind), count, percent(count, unsafe_reports))?;
+        }
+
+        writeln!(out, "First violations by level index:")?;
+        write_histogram(out, &self.first_positions)?;
+
+        writeln!(out, "Reports rescued by removing level:")?;
+        write_histogram(out, &self.rescued_by)
+    }
+}
+
+fn add_counts<K: Ord>(counts: &mut BTreeMap<K, usize>, other: BTreeMap<K, usize>) {
+    for (key, count) in other {
+        *counts.entry(key).or_insert(0) += count;
+    }
+}
+
+fn write_histogram<W: Write, K: std::fmt::Display>(out: &mut W, counts: &BTreeMap<K, usize>) -> io::Result<()> {
+    // Bars are scaled so the most common value gets 40 characters
+    let most = counts.values().copied().max().unwrap_or(0);
+    let total: usize = counts.values().sum();
+    for (key, &count) in counts {
+        let bar = "#".repeat((count * 40).div_ceil(most));
+        writeln!(out, "  {:>6}: {:>8} {:>7} {}", key, count, percent(count, total), bar)?;
+    }
+    Ok(())
+}
+
+fn percent(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", count as f64 * 100.0 / total as f64)
+}
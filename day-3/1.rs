@@ -1,89 +1,53 @@
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufWriter, Read, Write};
 
+mod feed;
+mod instructions;
+#[cfg(test)]
+#[path = "../common/rng.rs"]
+mod rng;
+mod scanner;
 
-fn find_multiplication(line: &str) -> Vec<(i32, i32)> {
-    let mut results = Vec::new();
-    let mut i = 0;
-    let chars: Vec<char> = line.chars().collect();
+use feed::PlainLines;
+use instructions::{Conditionals, Registry, Semantics};
+use scanner::{LineBreaks, Scanner};
 
-    while i < chars.len() {
-        // Look for "mul(" pattern
-        if i + 3 < chars.len() && 
-           chars[i] == 'm' && 
-           chars[i + 1] == 'u' && 
-           chars[i + 2] == 'l' && 
-           chars[i + 3] == '(' {
-            
-            i += 4; // Move past "mul("
-            let mut num1_str = String::new();
-            let mut num2_str = String::new();
-            
-            // Get first number
-            while i < chars.len() && chars[i].is_ascii_digit() {
-                num1_str.push(chars[i]);
-                i += 1;
-            }
-            
-            // Check for comma
-            if i < chars.len() && chars[i] == ',' {
-                i += 1;
-                
-                // Get second number
-                while i < chars.len() && chars[i].is_ascii_digit() {
-                    num2_str.push(chars[i]);
-                    i += 1;
-                }
-                
-                // Check for closing parenthesis
-                if i < chars.len() && chars[i] == ')' {
-                    // Parse numbers and validate
-                    if let (Ok(x), Ok(y)) = (num1_str.parse::<i32>(), num2_str.parse::<i32>()) {
-                        if (1..=999).contains(&x) && (1..=999).contains(&y) {
-                            results.push((x, y));
-                        }
-                    }
-                }
+// Part 1 only needs the scanner's tokens, so it adds up the products itself
+// rather than going through a.rs's driver and machine. Writes the products
+// to `output` in the plain format.
+fn add_products<R: Read, W: Write>(input: R, output: &mut W, registry: &Registry) -> io::Result<i64> {
+    let mut total_sum = 0;
+    let mut scanner = Scanner::new(registry, LineBreaks::Skip);
+    let mut lines = PlainLines::default();
+
+    feed::each_byte(input, |byte| {
+        lines.byte(output, byte)?;
+        if let Some(Ok(token)) = scanner.push(byte) {
+            if let Semantics::Value(evaluate) = registry[token.instruction].semantics {
+                total_sum += evaluate(token.operands());
+                lines.instruction(output, registry, &token)?;
             }
         }
-        i += 1;
-    }
-    results
-}
+        Ok(())
+    })?;
+    lines.finish(output)?;
 
-fn process_file(input_path: &str, output_path: &str) -> io::Result<i32> {
-    let mut total_sum = 0;
+    Ok(total_sum)
+}
 
+fn process_file(input_path: &str, output_path: &str, registry: &Registry) -> io::Result<i64> {
     // Open input file for reading
     let input_file = File::open(input_path)?;
-    let reader = BufReader::new(input_file);
 
     // Open output file for writing
-    let mut output_file = OpenOptions::new()
+    let mut output_file = BufWriter::new(OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(output_path)?;
+        .open(output_path)?);
 
-    // Process each line
-    for line in reader.lines() {
-        let line = line?;
-        let multiplications = parse_line(&line, &mut enabled);
-
-        // If we found any valid (and enabled) multiplications
-        if !multiplications.is_empty() {
-            // Add all multiplications to total
-            for (x, y) in &multiplications {
-                total_sum += x * y;
-            }
-            // Write only the mul expressions to the output file
-            let mul_expressions = multiplications.iter()
-                .map(|(x, y)| format!("mul({},{})", x, y))
-                .collect::<Vec<String>>()
-                .join(" ");
-            writeln!(output_file, "{}", mul_expressions)?;
-        }
-    }
+    let total_sum = add_products(input_file, &mut output_file, registry)?;
+    output_file.flush()?;
 
     Ok(total_sum)
 }
@@ -100,10 +64,40 @@ fn main() -> io::Result<()> {
     let input_file = &args[1];
     let output_file = "output.txt";
     
-    match process_file(input_file, output_file) {
-        Ok(sum) => println!("Total sum of all multiplications: {}", sum),
+    // Part 1: every mul counts, do() and don't() are ignored
+    let registry = Registry::puzzle(Conditionals::Ignored);
+    match process_file(input_file, output_file, &registry) {
+        Ok(total) => println!("Total sum of all multiplications: {}", total),
         Err(e) => eprintln!("Error processing file: {}", e),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str) -> (i64, String) {
+        let mut output = Vec::new();
+        let total = add_products(text.as_bytes(), &mut output, &Registry::puzzle(Conditionals::Ignored)).expect("reading from memory");
+        (total, String::from_utf8(output).expect("UTF-8 output"))
+    }
+
+    #[test]
+    fn example() {
+        let text = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+        assert_eq!(run(text), (161, "mul(2,4) mul(5,5) mul(11,8) mul(8,5)\n".to_string()));
+    }
+
+    // Line breaks are skipped, so a mul cut by one still counts, on the line
+    // it ends on. Lines without products leave no line, and the last line
+    // needs no newline.
+    #[test]
+    fn products_across_lines() {
+        let text = "mul(2,3)mu\nl(4,\n5)x\nnothing here\n\nmul(1000,1)mul(-1,2)\nmul(7,\n\n8)do()mul(1,1)";
+        assert_eq!(run(text), (6 + 20 + 56 + 1, "mul(2,3)\nmul(4,5)\nmul(7,8) mul(1,1)\n".to_string()));
+        assert_eq!(run(""), (0, String::new()));
+        assert_eq!(run("\n\nmul(3,3)\n"), (9, "mul(3,3)\n".to_string()));
+    }
+}
@@ -1,138 +1,149 @@
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
-
-fn parse_line(line: &str, enabled: &mut bool) -> Vec<(i32, i32)> {
-    let mut results = Vec::new();
-    let chars: Vec<char> = line.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        // Check for "do("
-        if i + 3 <= chars.len() && &chars[i..(i+3)] == ['d', 'o', '('] {
-            // Found do()
-            *enabled = true;
-            i += 3;
-            continue;
-        }
-
-        // Check for "don't("
-        // We expect 'd', 'o', 'n', ''', 't', '(' in sequence
-        if i + 6 <= chars.len() 
-            && chars[i] == 'd' 
-            && chars[i+1] == 'o' 
-            && chars[i+2] == 'n' 
-            && chars[i+3] == '\'' 
-            && chars[i+4] == 't' 
-            && chars[i+5] == '(' {
-            // Found don't()
-            *enabled = false;
-            i += 6;
-            continue;
-        }
-
-        // Look for "mul("
-        if i + 4 <= chars.len() 
-            && chars[i] == 'm' 
-            && chars[i+1] == 'u' 
-            && chars[i+2] == 'l' 
-            && chars[i+3] == '(' {
-
-            i += 4; // Move past "mul("
-            let mut num1_str = String::new();
-            let mut num2_str = String::new();
-
-            // Get first number
-            while i < chars.len() && chars[i].is_ascii_digit() {
-                num1_str.push(chars[i]);
-                i += 1;
-            }
-
-            // Check for comma
-            if i < chars.len() && chars[i] == ',' {
-                i += 1;
-
-                // Get second number
-                while i < chars.len() && chars[i].is_ascii_digit() {
-                    num2_str.push(chars[i]);
-                    i += 1;
-                }
-
-                if i < chars.len() && chars[i] == ')' {
-                    // Parse numbers and validate
-                    if let (Ok(x), Ok(y)) = (num1_str.parse::<i32>(), num2_str.parse::<i32>()) {
-                        if (1..=999).contains(&x) && (1..=999).contains(&y) {
-                            // Only add results if currently enabled
-                            if *enabled {
-                                results.push((x, y));
-                            }
-                        }
-                    }
-                }
-            }
-            i += 1;
-            continue;
-        }
-
-        i += 1;
-    }
-
-    results
-}
-
-fn process_file(input_path: &str, output_path: &str) -> io::Result<i32> {
-    let mut total_sum = 0;
-    let mut enabled = true; // At the beginning of the program, mul is enabled.
-
-    // Open input file for reading
-    let input_file = File::open(input_path)?;
-    let reader = BufReader::new(input_file);
-
-    // Open output file for writing
-    let mut output_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(output_path)?;
-
-    // Process each line
-    for line in reader.lines() {
-        let line = line?;
-        let multiplications = parse_line(&line, &mut enabled);
-
-        // If we found any valid (and enabled) multiplications
-        if !multiplications.is_empty() {
-            // Add all multiplications to total
-            for (x, y) in &multiplications {
-                total_sum += x * y;
-            }
-            // Write only the mul expressions to the output file
-            let mul_expressions = multiplications.iter()
-                .map(|(x, y)| format!("mul({},{})", x, y))
-                .collect::<Vec<String>>()
-                .join(" ");
-            writeln!(output_file, "{}", mul_expressions)?;
-        }
-    }
-
-    Ok(total_sum)
+use std::io;
+
+mod diagnostics;
+mod driver;
+mod feed;
+mod instructions;
+mod listing;
+#[cfg(test)]
+#[path = "../common/rng.rs"]
+mod rng;
+mod scanner;
+mod vm;
+
+use diagnostics::Diagnostics;
+use instructions::{Conditionals, OperandGrammar, Registry};
+use listing::Format;
+use scanner::LineBreaks;
+use vm::Policy;
+
+const USAGE: &str = "<input_file> [--conditionals exact|prefix] [--line-breaks skip|corrupt] \
+[--instructions puzzle|extended] \
+[--policy sum-of-products|product-of-sums|regions] [--dump] [--operands SPEC] [--rejections|--near-misses] \
+[--format plain|listing]";
+
+struct Options {
+    input_file: String,
+    // The instructions recognized, with the do() and don't() matching chosen
+    registry: Registry,
+    // Whether an instruction may continue on the next line
+    line_breaks: LineBreaks,
+    // What running the recovered instructions computes
+    policy: Box<dyn Policy>,
+    // Print the machine's final state
+    dump: bool,
+    // Print every rejected candidate with the reason
+    rejections: bool,
+    // Print near misses by class, with counts
+    near_misses: bool,
+    // Of output.txt
+    format: Format,
 }
 
 fn main() -> io::Result<()> {
 
     let args: Vec<String> = std::env::args().collect();
     
-    if args.len() != 2 {
-        eprintln!("Usage: {} <input_file>", args[0]);
+    if args.len() < 2 {
+        eprintln!("Usage: {} {}", args[0], USAGE);
         std::process::exit(1);
     }
-    
-    let input_file = &args[1];
+
+    let options = match parse_options(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Usage: {} {}", args[0], USAGE);
+            std::process::exit(1);
+        }
+    };
     let output_file = "output.txt";
     
-    match process_file(input_file, output_file) {
-        Ok(sum) => println!("Total sum of all multiplications: {}", sum),
+    // Part 2: value instructions only count while enabled
+    let mut policy = options.policy;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut diagnostics = if options.near_misses {
+        Some(Diagnostics::near_misses(&mut out, options.line_breaks))
+    } else if options.rejections {
+        Some(Diagnostics::rejections(&mut out))
+    } else {
+        None
+    };
+    match driver::process_file(&options.input_file, output_file, &options.registry, options.line_breaks, policy.as_mut(), options.format, diagnostics.as_mut()) {
+        Ok(machine) => {
+            policy.report(&machine, &mut out)?;
+            if options.dump {
+                machine.dump(&mut out)?;
+            }
+        }
         Err(e) => eprintln!("Error processing file: {}", e),
     }
 
     Ok(())
 }
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut input_file = None;
+    let mut conditionals = Conditionals::Exact;
+    let mut line_breaks = LineBreaks::Skip;
+    let mut extended = false;
+    let mut policy: Box<dyn Policy> = Box::new(vm::SumOfProducts);
+    let mut dump = false;
+    let mut grammar = OperandGrammar::PUZZLE;
+    let mut rejections = false;
+    let mut near_misses = false;
+    let mut format = Format::Plain;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--conditionals" => {
+                let value = iter.next().ok_or("--conditionals needs a value")?;
+                conditionals = Conditionals::parse(value).ok_or(format!("unknown conditionals: {}", value))?;
+            }
+            "--line-breaks" => {
+                let value = iter.next().ok_or("--line-breaks needs a value")?;
+                line_breaks = LineBreaks::parse(value).ok_or(format!("unknown line break handling: {}", value))?;
+            }
+            "--instructions" => {
+                extended = match iter.next().ok_or("--instructions needs a value")?.as_str() {
+                    "puzzle" => false,
+                    "extended" => true,
+                    value => return Err(format!("unknown instruction set: {}", value)),
+                };
+            }
+            "--policy" => {
+                let value = iter.next().ok_or("--policy needs a value")?;
+                policy = vm::policy(value).ok_or(format!("unknown policy: {}", value))?;
+            }
+            "--dump" => dump = true,
+            "--operands" => {
+                let value = iter.next().ok_or("--operands needs a grammar")?;
+                grammar = OperandGrammar::parse(value)?;
+            }
+            "--rejections" => rejections = true,
+            "--near-misses" => near_misses = true,
+            "--format" => {
+                let value = iter.next().ok_or("--format needs a value")?;
+                format = Format::parse(value).ok_or(format!("unknown format: {}", value))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => input_file = Some(arg.clone()),
+        }
+    }
+
+    let mut registry = if extended { Registry::extended(conditionals) } else { Registry::puzzle(conditionals) };
+    registry.set_operands(grammar);
+
+    Ok(Options {
+        input_file: input_file.ok_or("missing input file")?,
+        registry,
+        line_breaks,
+        policy,
+        dump,
+        rejections,
+        near_misses,
+        format,
+    })
+}
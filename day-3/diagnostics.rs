@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::instructions::{Instruction, OperandError, Registry, MAX_NAME};
+use crate::scanner::{LineBreaks, Reason, Rejected};
+
+// Near-miss diagnostics. Candidates the scanner rejects are sorted into
+// classes by what went wrong with them. Names followed by something other
+// than an opening parenthesis, as in mul[3,7] or mul ( 2 , 4 ), never become
+// candidates, so they are looked for on the side. The counts per class show
+// which recovery rules turn away the most.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Class {
+    // mul[3,7], mul(32,64]
+    WrongBracket,
+    // mul ( 2 , 4 )
+    Whitespace,
+    // An operand the grammar doesn't allow, like 1000 or 0
+    OutOfRange,
+    // Fewer operands than the instruction takes, like mul(24)
+    MissingComma,
+    // Cut off by a stray byte or the end of the input, or a name running
+    // into the next word, like do_not_mul(5,5)
+    Truncated,
+    // Anything else, like an extra operand or an empty one
+    Other,
+}
+
+const CLASSES: [Class; 6] = [
+    Class::WrongBracket,
+    Class::Whitespace,
+    Class::OutOfRange,
+    Class::MissingComma,
+    Class::Truncated,
+    Class::Other,
+];
+
+impl Class {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::WrongBracket => "wrong bracket",
+            Class::Whitespace => "whitespace",
+            Class::OutOfRange => "out of range",
+            Class::MissingComma => "missing comma",
+            Class::Truncated => "truncated",
+            Class::Other => "other",
+        }
+    }
+}
+
+fn is_bracket(byte: u8) -> bool {
+    matches!(byte, b'[' | b']' | b'{' | b'}' | b'<' | b'>')
+}
+
+/// What kind of near miss a rejected candidate is
+pub fn classify(instruction: &Instruction, reason: Reason) -> Class {
+    match reason {
+        Reason::Unexpected(byte) if is_bracket(byte) => Class::WrongBracket,
+        Reason::Unexpected(byte) if byte.is_ascii_whitespace() => Class::Whitespace,
+        // A comma past the last operand
+        Reason::Unexpected(b',') => Class::Other,
+        Reason::Unexpected(_) | Reason::Truncated => Class::Truncated,
+        Reason::Operand(_, OperandError::Empty) => Class::Other,
+        Reason::Operand(..) => Class::OutOfRange,
+        Reason::Arity(count) if count > 0 && count < *instruction.arity.start() => Class::MissingComma,
+        Reason::Arity(_) => Class::Other,
+    }
+}
+
+/// Where driver::process_file reports what it turned away
+pub struct Diagnostics<'w> {
+    out: &'w mut dyn Write,
+    // Classify near misses, rather than only list the rejected candidates
+    classify: bool,
+    line_breaks: LineBreaks,
+    // The last bytes the scanner saw and their offsets
+    recent: Vec<(u8, u64)>,
+    offset: u64,
+    // A name followed by whitespace, waiting for an opening parenthesis:
+    // its instruction and where it starts
+    spaced: Option<(usize, u64)>,
+    // Class name -> near misses
+    pub counts: BTreeMap<&'static str, usize>,
+}
+
+impl<'w> Diagnostics<'w> {
+    /// Lists the rejected candidates as they are
+    pub fn rejections(out: &'w mut dyn Write) -> Self {
+        Diagnostics::new(out, false, LineBreaks::Skip)
+    }
+
+    /// Lists and counts near misses by class
+    pub fn near_misses(out: &'w mut dyn Write, line_breaks: LineBreaks) -> Self {
+        Diagnostics::new(out, true, line_breaks)
+    }
+
+    fn new(out: &'w mut dyn Write, classify: bool, line_breaks: LineBreaks) -> Self {
+        Diagnostics {
+            out,
+            classify,
+            line_breaks,
+            recent: Vec::with_capacity(MAX_NAME + 1),
+            offset: 0,
+            spaced: None,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Sees the same bytes as the scanner, for the names it doesn't start
+    /// a candidate for
+    pub fn push(&mut self, registry: &Registry, byte: u8) -> io::Result<()> {
+        let at = self.offset;
+        self.offset += 1;
+        if !self.classify || self.line_breaks == LineBreaks::Skip && (byte == b'\n' || byte == b'\r') {
+            return Ok(());
+        }
+
+        if let Some((index, start)) = self.spaced {
+            if byte == b'(' {
+                self.spaced = None;
+                self.report(Class::Whitespace, registry[index].name, start, format_args!("whitespace before '('"))?;
+            } else if !byte.is_ascii_whitespace() {
+                self.spaced = None;
+            }
+        } else if let Some((index, start)) = self.name_before(registry) {
+            let name = registry[index].name;
+            match byte {
+                b'[' | b'{' | b'<' => {
+                    self.report(Class::WrongBracket, name, start, format_args!("'{}' instead of '('", byte as char))?;
+                }
+                b'_' => self.report(Class::Truncated, name, start, format_args!("'_' right after the name"))?,
+                _ if byte.is_ascii_whitespace() => self.spaced = Some((index, start)),
+                _ => {}
+            }
+        }
+
+        if self.recent.len() == MAX_NAME {
+            self.recent.remove(0);
+        }
+        self.recent.push((byte, at));
+        Ok(())
+    }
+
+    // The longest name the recent bytes end with, and where it starts
+    fn name_before(&self, registry: &Registry) -> Option<(usize, u64)> {
+        let ends_with = |name: &[u8]| {
+            name.len() <= self.recent.len()
+                && self.recent[self.recent.len() - name.len()..].iter().map(|&(b, _)| b).eq(name.iter().copied())
+        };
+        let index = (0..registry.len())
+            .filter(|&i| ends_with(registry[i].name.as_bytes()))
+            .max_by_key(|&i| registry[i].name.len())?;
+        Some((index, self.recent[self.recent.len() - registry[index].name.len()].1))
+    }
+
+    pub fn rejected(&mut self, registry: &Registry, rejected: &Rejected) -> io::Result<()> {
+        if !self.classify {
+            let name = registry[rejected.instruction].name;
+            return writeln!(self.out, "rejected {}( at byte {}: {}", name, rejected.start, rejected.reason);
+        }
+        let instruction = &registry[rejected.instruction];
+        let class = classify(instruction, rejected.reason);
+        self.report(class, &format!("{}(", instruction.name), rejected.start, format_args!("{}", rejected.reason))
+    }
+
+    fn report(&mut self, class: Class, text: &str, start: u64, detail: fmt::Arguments) -> io::Result<()> {
+        *self.counts.entry(class.name()).or_insert(0) += 1;
+        writeln!(self.out, "{}: {} at byte {}: {}", class.name(), text, start, detail)
+    }
+
+    /// The counts per class, once the input is done
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.classify {
+            return Ok(());
+        }
+        writeln!(self.out, "Near misses: {}", self.counts.values().sum::<usize>())?;
+        for class in CLASSES.iter() {
+            let count = self.counts.get(class.name()).copied().unwrap_or(0);
+            writeln!(self.out, "  {:>13}: {}", class.name(), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::Conditionals;
+    use crate::scanner::Scanner;
+
+    // Texts and the near misses the puzzle's instructions have to find in them
+    const NEAR_MISS_CASES: [(&str, &[Class]); 12] = [
+        ("mul[3,7]", &[Class::WrongBracket]),
+        ("mul(32,64]", &[Class::WrongBracket]),
+        ("mul ( 2 , 4 )", &[Class::Whitespace]),
+        ("mul( 2,4)", &[Class::Whitespace]),
+        ("mul(1000,5)mul(-5,3)", &[Class::OutOfRange, Class::OutOfRange]),
+        ("mul(24)", &[Class::MissingComma]),
+        ("do_not_mul(5,5)", &[Class::Truncated]),
+        ("mul(4*5)mul(4,", &[Class::Truncated, Class::Truncated]),
+        ("mul(1,2,3)mul(,5)", &[Class::Other, Class::Other]),
+        ("mu\nl[1,2]", &[Class::WrongBracket]),
+        ("multiply mul and don't do it", &[]),
+        (
+            "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))",
+            &[Class::WrongBracket, Class::Truncated, Class::WrongBracket],
+        ),
+    ];
+
+    #[test]
+    fn near_miss_cases() {
+        let registry = Registry::puzzle(Conditionals::Exact);
+        for &(text, expected) in NEAR_MISS_CASES.iter() {
+            let mut sink = Vec::new();
+            let mut diagnostics = Diagnostics::near_misses(&mut sink, LineBreaks::Skip);
+            let mut scanner = Scanner::new(&registry, LineBreaks::Skip);
+            for byte in text.bytes() {
+                diagnostics.push(&registry, byte).expect("writing to a Vec");
+                if let Some(Err(rejected)) = scanner.push(byte) {
+                    diagnostics.rejected(&registry, &rejected).expect("writing to a Vec");
+                }
+            }
+            if let Some(rejected) = scanner.finish() {
+                diagnostics.rejected(&registry, &rejected).expect("writing to a Vec");
+            }
+
+            let mut counts = BTreeMap::new();
+            for class in expected {
+                *counts.entry(class.name()).or_insert(0) += 1;
+            }
+            assert_eq!(diagnostics.counts, counts, "near misses in {:?}", text);
+        }
+    }
+}
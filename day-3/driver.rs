@@ -0,0 +1,92 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+
+use crate::diagnostics::Diagnostics;
+use crate::feed::{self, PlainLines};
+use crate::instructions::Registry;
+use crate::listing::{self, Format, Window};
+use crate::scanner::{LineBreaks, Scanner};
+use crate::vm::{Machine, Policy};
+
+// Runs a whole input file: the scanner's tokens go to the machine and the
+// output file, its rejections to the diagnostics.
+
+/// Streams the input through the scanner in fixed-size reads and runs what
+/// it recovers on a Machine. In the plain format the output has the enabled
+/// value instructions of each input line, by the line they end on; the
+/// listing has every instruction. What was turned away goes to
+/// `diagnostics` when given.
+pub fn process_file(
+    input_path: &str,
+    output_path: &str,
+    registry: &Registry,
+    line_breaks: LineBreaks,
+    policy: &mut dyn Policy,
+    format: Format,
+    mut diagnostics: Option<&mut Diagnostics>,
+) -> io::Result<Machine> {
+    let mut machine = Machine::new(policy);
+
+    // Open input file for reading
+    let input_file = File::open(input_path)?;
+
+    // Open output file for writing
+    let mut output_file = BufWriter::new(OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path)?);
+
+    let mut scanner = Scanner::new(registry, line_breaks);
+    let mut lines = PlainLines::default();
+    // Text of the current candidate, for the listing
+    let mut window = Window::default();
+    if format == Format::Listing {
+        listing::write_header(&mut output_file)?;
+    }
+
+    feed::each_byte(input_file, |byte| {
+        lines.byte(&mut output_file, byte)?;
+        if format == Format::Listing {
+            window.push(byte);
+        }
+        if let Some(diagnostics) = diagnostics.as_mut() {
+            diagnostics.push(registry, byte)?;
+        }
+        let found = scanner.push(byte);
+        let token = match found {
+            Some(Ok(token)) => Some(token),
+            Some(Err(rejected)) => {
+                if let Some(diagnostics) = diagnostics.as_mut() {
+                    diagnostics.rejected(registry, &rejected)?;
+                }
+                None
+            }
+            None => None,
+        };
+        if let Some(token) = token {
+            let enabled = machine.enabled;
+            let counted = machine.execute(registry, &token, policy);
+            match format {
+                Format::Listing => listing::write_entry(&mut output_file, &window, registry, &token, enabled, &machine)?,
+                // Write only the value expressions that ran to the output file
+                Format::Plain if counted => lines.instruction(&mut output_file, registry, &token)?,
+                Format::Plain => {}
+            }
+        }
+        if format == Format::Listing {
+            window.keep_from(scanner.candidate_start());
+        }
+        Ok(())
+    })?;
+    if let Some(diagnostics) = diagnostics {
+        if let Some(rejected) = scanner.finish() {
+            diagnostics.rejected(registry, &rejected)?;
+        }
+        diagnostics.finish()?;
+    }
+    lines.finish(&mut output_file)?;
+    output_file.flush()?;
+
+    Ok(machine)
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Read, Write};
+
+use crate::instructions::Registry;
+use crate::scanner::Token;
+
+// The byte loop and the plain output format, shared by part 1 and a.rs's
+// driver so both read the input and lay out output.txt the same way.
+
+/// Hands every byte of `input` to `each`, in fixed-size reads
+pub fn each_byte<R: Read, F: FnMut(u8) -> io::Result<()>>(mut input: R, mut each: F) -> io::Result<()> {
+    let mut buffer = [0u8; 1 << 16];
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &byte in &buffer[..read] {
+            each(byte)?;
+        }
+    }
+}
+
+/// The plain output format: the instructions of each input line, separated
+/// by spaces, on the line they end on. Input lines without any are left out.
+#[derive(Default)]
+pub struct PlainLines {
+    // Whether something was written for the current input line
+    line_started: bool,
+}
+
+impl PlainLines {
+    /// Takes every input byte, before the scanner sees it
+    pub fn byte<W: Write>(&mut self, out: &mut W, byte: u8) -> io::Result<()> {
+        if byte == b'\n' && self.line_started {
+            writeln!(out)?;
+            self.line_started = false;
+        }
+        Ok(())
+    }
+
+    pub fn instruction<W: Write>(&mut self, out: &mut W, registry: &Registry, token: &Token) -> io::Result<()> {
+        if self.line_started {
+            write!(out, " ")?;
+        }
+        self.line_started = true;
+        write_instruction(out, registry, token)
+    }
+
+    /// Ends the last line
+    pub fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if self.line_started {
+            writeln!(out)?;
+            self.line_started = false;
+        }
+        Ok(())
+    }
+}
+
+pub fn write_instruction<W: Write>(out: &mut W, registry: &Registry, token: &Token) -> io::Result<()> {
+    write!(out, "{}(", registry[token.instruction].name)?;
+    for (i, operand) in token.operands().iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "{}", operand)?;
+    }
+    write!(out, ")")
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::ops::{Index, RangeInclusive};
+
+// The instructions the scanner recognizes. Each one is written as its name,
+// an opening parenthesis, comma separated operands and a closing
+// parenthesis, and says what it does once recovered.
+//
+// Part 1 builds this module too but never sees a.rs's options, so what
+// only they reach is marked #[allow(dead_code)].
+
+// Most operands any instruction can take
+pub const MAX_OPERANDS: usize = 4;
+
+// Longest instruction name
+pub const MAX_NAME: usize = 15;
+
+// How do() and don't() are recognized
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Conditionals {
+    // Part 1: every mul counts
+    Ignored,
+    // Only the complete do() and don't()
+    Exact,
+    // Anything starting with do( or don't(, as the original part 2 did
+    #[allow(dead_code)]
+    Prefix,
+}
+
+impl Conditionals {
+    #[allow(dead_code)]
+    pub fn parse(name: &str) -> Option<Conditionals> {
+        match name {
+            "exact" => Some(Conditionals::Exact),
+            "prefix" => Some(Conditionals::Prefix),
+            _ => None,
+        }
+    }
+}
+
+// What is wrong with an operand
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OperandError {
+    Empty,
+    TooFewDigits,
+    TooManyDigits,
+    LeadingZero,
+    Zero,
+    // A sign where the grammar has none
+    Sign,
+    TooLarge,
+}
+
+impl fmt::Display for OperandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            OperandError::Empty => "is empty",
+            OperandError::TooFewDigits => "has too few digits",
+            OperandError::TooManyDigits => "has too many digits",
+            OperandError::LeadingZero => "has a leading zero",
+            OperandError::Zero => "is zero",
+            OperandError::Sign => "has a sign",
+            OperandError::TooLarge => "is too large",
+        };
+        f.write_str(text)
+    }
+}
+
+/// What an operand may look like
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OperandGrammar {
+    pub min_digits: usize,
+    // None for any number of digits
+    pub max_digits: Option<usize>,
+    // Whether more than one digit may start with 0, as in 007
+    pub leading_zeros: bool,
+    pub zero: bool,
+    // Whether a - or + may come before the digits
+    pub sign: bool,
+    // Largest absolute value
+    pub max: i32,
+}
+
+impl OperandGrammar {
+    /// The puzzle's 1-3 digit numbers
+    pub const PUZZLE: OperandGrammar = OperandGrammar {
+        min_digits: 1,
+        max_digits: Some(3),
+        leading_zeros: true,
+        zero: true,
+        sign: false,
+        max: 999,
+    };
+
+    /// Any run of digits with a value in 1..=999, what the original
+    /// scanners accepted
+    #[allow(dead_code)]
+    pub const ORIGINAL: OperandGrammar = OperandGrammar {
+        min_digits: 1,
+        max_digits: None,
+        leading_zeros: true,
+        zero: false,
+        sign: false,
+        max: 999,
+    };
+
+    /// `puzzle`, `original`, or comma separated changes to the puzzle's
+    /// grammar: `digits=MIN-MAX` (MAX may be `any`), `leading-zeros=yes|no`,
+    /// `zero=yes|no`, `sign=yes|no` and `max=N`
+    #[allow(dead_code)]
+    pub fn parse(spec: &str) -> Result<OperandGrammar, String> {
+        match spec {
+            "puzzle" => return Ok(OperandGrammar::PUZZLE),
+            "original" => return Ok(OperandGrammar::ORIGINAL),
+            _ => {}
+        }
+
+        let mut grammar = OperandGrammar::PUZZLE;
+        for setting in spec.split(',') {
+            let (key, value) = setting.split_once('=').ok_or(format!("expected key=value: {}", setting))?;
+            let invalid = || format!("invalid value for {}: {}", key, value);
+            let flag = || match value {
+                "yes" => Ok(true),
+                "no" => Ok(false),
+                _ => Err(invalid()),
+            };
+            match key {
+                "digits" => {
+                    let (min, max) = value.split_once('-').ok_or_else(invalid)?;
+                    grammar.min_digits = min.parse().map_err(|_| invalid())?;
+                    grammar.max_digits = match max {
+                        "any" => None,
+                        _ => Some(max.parse().map_err(|_| invalid())?),
+                    };
+                }
+                "leading-zeros" => grammar.leading_zeros = flag()?,
+                "zero" => grammar.zero = flag()?,
+                "sign" => grammar.sign = flag()?,
+                "max" => grammar.max = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown operand setting: {}", key)),
+            }
+        }
+
+        if grammar.min_digits < 1 || grammar.max_digits.is_some_and(|max| max < grammar.min_digits) {
+            return Err("digits must satisfy 1 <= MIN <= MAX".to_string());
+        }
+        if grammar.max < 0 {
+            return Err("max can't be negative".to_string());
+        }
+        Ok(grammar)
+    }
+
+    /// Checks an operand from its number of digits, whether the first one
+    /// is 0 and its value, None when it doesn't fit in an i32
+    pub fn check_digits(&self, digits: usize, first_zero: bool, value: Option<i64>) -> Result<i32, OperandError> {
+        if digits == 0 {
+            return Err(OperandError::Empty);
+        }
+        if digits < self.min_digits {
+            return Err(OperandError::TooFewDigits);
+        }
+        if self.max_digits.is_some_and(|max| digits > max) {
+            return Err(OperandError::TooManyDigits);
+        }
+        if digits > 1 && first_zero && !self.leading_zeros {
+            return Err(OperandError::LeadingZero);
+        }
+        match value {
+            Some(0) if !self.zero => Err(OperandError::Zero),
+            Some(v) if v <= self.max as i64 => Ok(v as i32),
+            _ => Err(OperandError::TooLarge),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Semantics {
+    // Counts towards the total while enabled
+    Value(fn(&[i32]) -> i64),
+    // Switches counting on or off
+    #[allow(dead_code)]
+    Enable(bool),
+    // Recognized, but does nothing
+    #[allow(dead_code)]
+    Ignore,
+}
+
+#[derive(Clone)]
+pub struct Instruction {
+    pub name: &'static str,
+    pub arity: RangeInclusive<usize>,
+    pub operands: OperandGrammar,
+    pub semantics: Semantics,
+    // False when the name and the opening parenthesis are enough
+    pub closed: bool,
+}
+
+impl Instruction {
+    pub fn new(name: &'static str, arity: RangeInclusive<usize>, semantics: Semantics) -> Self {
+        Instruction { name, arity, operands: OperandGrammar::PUZZLE, semantics, closed: true }
+    }
+}
+
+/// Instructions by index, the scanner's tokens refer to them that way
+#[derive(Clone, Default)]
+pub struct Registry {
+    instructions: Vec<Instruction>,
+}
+
+impl Registry {
+    /// The puzzle's instructions: mul and, unless ignored, do() and don't()
+    pub fn puzzle(conditionals: Conditionals) -> Registry {
+        let mut registry = Registry::default();
+        registry.add(Instruction::new("mul", 2..=2, Semantics::Value(|ops| ops[0] as i64 * ops[1] as i64)));
+        if conditionals != Conditionals::Ignored {
+            let closed = conditionals == Conditionals::Exact;
+            registry.add(Instruction { closed, ..Instruction::new("do", 0..=0, Semantics::Enable(true)) });
+            registry.add(Instruction { closed, ..Instruction::new("don't", 0..=0, Semantics::Enable(false)) });
+        }
+        registry
+    }
+
+    /// The puzzle's instructions plus the others seen in corrupted memories
+    #[allow(dead_code)]
+    pub fn extended(conditionals: Conditionals) -> Registry {
+        let mut registry = Registry::puzzle(conditionals);
+        registry.add(Instruction::new("add", 2..=2, Semantics::Value(|ops| ops[0] as i64 + ops[1] as i64)));
+        registry.add(Instruction::new("sub", 2..=2, Semantics::Value(|ops| ops[0] as i64 - ops[1] as i64)));
+        for name in ["select", "where", "when", "from"] {
+            registry.add(Instruction::new(name, 0..=2, Semantics::Ignore));
+        }
+        registry
+    }
+
+    /// Names are unique, of lowercase letters, underscores and apostrophes,
+    /// and at most MAX_NAME bytes long
+    pub fn register(&mut self, instruction: Instruction) -> Result<(), String> {
+        let name = instruction.name;
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_lowercase() || b == b'_' || b == b'\'') {
+            return Err(format!("invalid instruction name: {:?}", name));
+        }
+        if name.len() > MAX_NAME {
+            return Err(format!("instruction name {} is longer than {} bytes", name, MAX_NAME));
+        }
+        if self.position(name).is_some() {
+            return Err(format!("instruction {} is already registered", name));
+        }
+        if *instruction.arity.end() > MAX_OPERANDS || instruction.arity.is_empty() {
+            return Err(format!("{} takes {:?} operands, at most {} are supported", name, instruction.arity, MAX_OPERANDS));
+        }
+        self.instructions.push(instruction);
+        Ok(())
+    }
+
+    /// Use `grammar` for the operands of every instruction
+    #[allow(dead_code)]
+    pub fn set_operands(&mut self, grammar: OperandGrammar) {
+        for instruction in &mut self.instructions {
+            instruction.operands = grammar;
+        }
+    }
+
+    // For the built-in sets, which are valid
+    fn add(&mut self, instruction: Instruction) {
+        self.register(instruction).expect("built-in instruction");
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.instructions.iter().position(|i| i.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Byte `i` of the text starting an instruction: its name and the
+    /// opening parenthesis
+    pub fn keyword_byte(&self, index: usize, i: usize) -> Option<u8> {
+        let name = self.instructions[index].name.as_bytes();
+        match i {
+            _ if i < name.len() => Some(name[i]),
+            _ if i == name.len() => Some(b'('),
+            _ => None,
+        }
+    }
+}
+
+impl Index<usize> for Registry {
+    type Output = Instruction;
+
+    fn index(&self, index: usize) -> &Instruction {
+        &self.instructions[index]
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+
+use crate::instructions::{Registry, Semantics};
+use crate::scanner::Token;
+use crate::vm::Machine;
+
+// The annotated listing: one line per recovered instruction with where it
+// is in the input, the text it was recovered from, whether it counted and
+// the running total, so the answer can be audited instruction by
+// instruction. Instructions skipped while disabled are listed too.
+
+/// What driver::process_file writes to the output file
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    // The enabled value instructions of each line, space separated
+    Plain,
+    // One annotated line per instruction
+    Listing,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "plain" => Some(Format::Plain),
+            "listing" => Some(Format::Listing),
+            _ => None,
+        }
+    }
+}
+
+/// The bytes of the candidate the scanner is working on, kept while the
+/// input streams past, and where in the input they start
+pub struct Window {
+    bytes: Vec<u8>,
+    // Byte offset of bytes[0]
+    start: u64,
+    // 1-based line and column of bytes[0]
+    line: u64,
+    column: u64,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window { bytes: Vec::new(), start: 0, line: 1, column: 1 }
+    }
+}
+
+impl Window {
+    pub fn push(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    /// Drops the bytes before `offset`, or all of them when the scanner
+    /// isn't in a candidate
+    pub fn keep_from(&mut self, offset: Option<u64>) {
+        let end = self.start + self.bytes.len() as u64;
+        let offset = offset.unwrap_or(end).clamp(self.start, end);
+        let dropped = (offset - self.start) as usize;
+        for &byte in &self.bytes[..dropped] {
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.bytes.drain(..dropped);
+        self.start = offset;
+    }
+
+    /// Line, column and text of a token, which starts where the window
+    /// does since its candidate was the one kept
+    fn locate(&self, token: &Token) -> (u64, u64, &[u8]) {
+        debug_assert_eq!(token.start, self.start);
+        let len = (token.end - self.start) as usize;
+        (self.line, self.column, &self.bytes[..len.min(self.bytes.len())])
+    }
+}
+
+pub fn write_header<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "{:>10} {:>12} {:<10} {:>12} {:>20}  text (operands)", "offset", "line:column", "state", "value", "total")
+}
+
+/// One line for `token`, after `machine` ran it. `enabled` is whether value
+/// instructions counted when it came.
+pub fn write_entry<W: Write>(
+    out: &mut W,
+    window: &Window,
+    registry: &Registry,
+    token: &Token,
+    enabled: bool,
+    machine: &Machine,
+) -> io::Result<()> {
+    let (line, column, text) = window.locate(token);
+    let (state, value) = match registry[token.instruction].semantics {
+        Semantics::Value(evaluate) => {
+            let state = if enabled { "enabled" } else { "disabled" };
+            (state, evaluate(token.operands()).to_string())
+        }
+        Semantics::Enable(true) => ("switch on", "-".to_string()),
+        Semantics::Enable(false) => ("switch off", "-".to_string()),
+        Semantics::Ignore => ("ignored", "-".to_string()),
+    };
+    let operands: Vec<String> = token.operands().iter().map(|o| o.to_string()).collect();
+    writeln!(
+        out,
+        "{:>10} {:>12} {:<10} {:>12} {:>20}  {} ({})",
+        token.start,
+        format!("{}:{}", line, column),
+        state,
+        value,
+        machine.accumulator,
+        printable(text),
+        operands.join(", ")
+    )
+}
+
+// Line breaks and other bytes that aren't printable ASCII escaped, so each
+// instruction stays on its line
+fn printable(text: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in text {
+        if byte == b' ' || byte.is_ascii_graphic() {
+            escaped.push(byte as char);
+        } else {
+            escaped.extend(byte.escape_ascii().map(|b| b as char));
+        }
+    }
+    escaped
+}
@@ -0,0 +1,605 @@
+use std::fmt;
+
+use crate::instructions::{Instruction, OperandError, OperandGrammar, Registry, MAX_NAME, MAX_OPERANDS};
+
+// Scanner for the corrupted memory, shared by both parts: part 1 only looks
+// for mul instructions, part 2 also follows do() and don't(). Which
+// instructions exist comes from a Registry.
+//
+// The scanner is fed one byte at a time and keeps its state between bytes,
+// so an instruction may span read buffers and, unless line breaks count as
+// corruption, lines.
+//
+// The line break choice and what the driver needs for the listing and the
+// diagnostics go unused in part 1 and carry #[allow(dead_code)].
+
+// What a line break inside an instruction does
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineBreaks {
+    // Skipped, as if the lines were one
+    Skip,
+    // Breaks the instruction like any other stray character, which is what
+    // scanning line by line did
+    #[allow(dead_code)]
+    Corrupt,
+}
+
+impl LineBreaks {
+    #[allow(dead_code)]
+    pub fn parse(name: &str) -> Option<LineBreaks> {
+        match name {
+            "skip" => Some(LineBreaks::Skip),
+            "corrupt" => Some(LineBreaks::Corrupt),
+            _ => None,
+        }
+    }
+}
+
+/// A recovered instruction
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Token {
+    // Index in the registry
+    pub instruction: usize,
+    operands: [i32; MAX_OPERANDS],
+    count: usize,
+    // Byte offsets of the start of the name and just past the end
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Token {
+    pub fn operands(&self) -> &[i32] {
+        &self.operands[..self.count]
+    }
+}
+
+// Why a candidate, an instruction name followed by its opening parenthesis,
+// didn't become an instruction
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reason {
+    // A byte that can't continue the instruction
+    Unexpected(u8),
+    // The input ended inside it
+    #[allow(dead_code)]
+    Truncated,
+    // Number of operands the instruction doesn't take
+    Arity(usize),
+    // 1-based operand and what is wrong with it
+    Operand(usize, OperandError),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::Unexpected(byte) => write!(f, "unexpected '{}'", byte.escape_ascii()),
+            Reason::Truncated => write!(f, "input ends inside it"),
+            Reason::Arity(count) => write!(f, "wrong number of operands ({})", count),
+            Reason::Operand(n, error) => write!(f, "operand {} {}", n, error),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rejected {
+    pub instruction: usize,
+    // Byte offset of the start of the name
+    pub start: u64,
+    pub reason: Reason,
+}
+
+// An operand as it is read: its sign, digits and value so far
+#[derive(Clone, Copy)]
+struct Operand {
+    sign: Option<u8>,
+    digits: usize,
+    first_zero: bool,
+    // None once it no longer fits in an i32
+    value: Option<i64>,
+}
+
+impl Operand {
+    const EMPTY: Operand = Operand { sign: None, digits: 0, first_zero: false, value: Some(0) };
+
+    fn push(&mut self, digit: u8) {
+        if self.digits == 0 {
+            self.first_zero = digit == b'0';
+        }
+        self.digits += 1;
+        self.value = self.value
+            .and_then(|v| v.checked_mul(10))
+            .map(|v| v + (digit - b'0') as i64)
+            .filter(|&v| v <= i32::MAX as i64);
+    }
+
+    fn check(&self, grammar: &OperandGrammar) -> Result<i32, OperandError> {
+        let value = grammar.check_digits(self.digits, self.first_zero, self.value)?;
+        Ok(if self.sign == Some(b'-') { -value } else { value })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    // The first `matched` bytes of the name and parenthesis of instruction
+    // `index` have been seen
+    Keyword { index: usize, matched: usize },
+    // After the opening parenthesis, with `count` operands started
+    Operands { index: usize, start: u64, operands: [Operand; MAX_OPERANDS], count: usize },
+}
+
+/// Recognizes instructions one byte at a time, without any allocation
+pub struct Scanner<'a> {
+    registry: &'a Registry,
+    line_breaks: LineBreaks,
+    state: State,
+    // Bytes pushed so far
+    offset: u64,
+    // Where the bytes of the name being matched were, which needn't be
+    // adjacent when line breaks are skipped
+    name_offsets: [u64; MAX_NAME + 1],
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(registry: &'a Registry, line_breaks: LineBreaks) -> Self {
+        Scanner { registry, line_breaks, state: State::Idle, offset: 0, name_offsets: [0; MAX_NAME + 1] }
+    }
+
+    /// Feeds the next byte and returns the instruction it completes or the
+    /// candidate it rejects, if any
+    pub fn push(&mut self, byte: u8) -> Option<Result<Token, Rejected>> {
+        let at = self.offset;
+        self.offset += 1;
+        if self.line_breaks == LineBreaks::Skip && (byte == b'\n' || byte == b'\r') {
+            return None;
+        }
+        self.step(byte, at)
+    }
+
+    /// Byte offset where the candidate being matched starts, if any
+    #[allow(dead_code)]
+    pub fn candidate_start(&self) -> Option<u64> {
+        match self.state {
+            State::Idle => None,
+            State::Keyword { .. } => Some(self.name_offsets[0]),
+            State::Operands { start, .. } => Some(start),
+        }
+    }
+
+    /// The candidate cut off by the end of the input, if any
+    #[allow(dead_code)]
+    pub fn finish(&mut self) -> Option<Rejected> {
+        let state = std::mem::replace(&mut self.state, State::Idle);
+        match state {
+            State::Operands { index, start, .. } => Some(Rejected { instruction: index, start, reason: Reason::Truncated }),
+            _ => None,
+        }
+    }
+
+    fn step(&mut self, byte: u8, at: u64) -> Option<Result<Token, Rejected>> {
+        match self.state {
+            State::Idle => {
+                self.start(byte, at);
+                None
+            }
+            State::Keyword { index, matched } => self.keyword(index, matched, byte, at),
+            State::Operands { index, start, operands, count } => self.operand(index, start, operands, count, byte, at),
+        }
+    }
+
+    fn start(&mut self, byte: u8, at: u64) {
+        let registry = self.registry;
+        self.state = match (0..registry.len()).find(|&i| registry.keyword_byte(i, 0) == Some(byte)) {
+            Some(index) => {
+                self.name_offsets[0] = at;
+                State::Keyword { index, matched: 1 }
+            }
+            None => State::Idle,
+        };
+    }
+
+    fn keyword(&mut self, index: usize, matched: usize, byte: u8, at: u64) -> Option<Result<Token, Rejected>> {
+        let registry = self.registry;
+        // Instructions sharing a prefix are told apart on the way
+        let next = (0..registry.len()).find(|&other| {
+            (0..matched).all(|i| registry.keyword_byte(other, i) == registry.keyword_byte(index, i))
+                && registry.keyword_byte(other, matched) == Some(byte)
+        });
+        match next {
+            // Names don't contain parentheses, so this completes the keyword
+            Some(other) if byte == b'(' => self.opened(other, at),
+            Some(other) => {
+                self.name_offsets[matched] = at;
+                self.state = State::Keyword { index: other, matched: matched + 1 };
+                None
+            }
+            None => {
+                // Another instruction may start inside the bytes matched so
+                // far, as in "fromul(", so they are fed again after the first
+                self.state = State::Idle;
+                let offsets = self.name_offsets;
+                for (i, &offset) in offsets.iter().enumerate().take(matched).skip(1) {
+                    self.feed_again(registry.keyword_byte(index, i), offset);
+                }
+                self.step(byte, at)
+            }
+        }
+    }
+
+    // Part of a name can't complete an instruction, only move the state
+    fn feed_again(&mut self, byte: Option<u8>, at: u64) {
+        if let Some(byte) = byte {
+            let found = self.step(byte, at);
+            debug_assert!(found.is_none());
+        }
+    }
+
+    fn opened(&mut self, index: usize, at: u64) -> Option<Result<Token, Rejected>> {
+        let start = self.name_offsets[0];
+        if !self.registry[index].closed {
+            self.state = State::Idle;
+            return Some(Ok(Token { instruction: index, operands: [0; MAX_OPERANDS], count: 0, start, end: at + 1 }));
+        }
+        self.state = State::Operands { index, start, operands: [Operand::EMPTY; MAX_OPERANDS], count: 0 };
+        None
+    }
+
+    fn operand(
+        &mut self,
+        index: usize,
+        start: u64,
+        mut operands: [Operand; MAX_OPERANDS],
+        mut count: usize,
+        byte: u8,
+        at: u64,
+    ) -> Option<Result<Token, Rejected>> {
+        let instruction = &self.registry[index];
+        let most = *instruction.arity.end();
+        let reject = |reason| Some(Err(Rejected { instruction: index, start, reason }));
+        // A stray byte ends the candidate and is skipped with it, like the
+        // original scanner did
+        self.state = State::Idle;
+        match byte {
+            b'0'..=b'9' if most > 0 => {
+                count = count.max(1);
+                operands[count - 1].push(byte);
+            }
+            b'-' | b'+' if most > 0 && (count == 0 || operands[count - 1].digits == 0 && operands[count - 1].sign.is_none()) => {
+                count = count.max(1);
+                if !instruction.operands.sign {
+                    return reject(Reason::Operand(count, OperandError::Sign));
+                }
+                operands[count - 1].sign = Some(byte);
+            }
+            b',' if count.max(1) < most => count = count.max(1) + 1,
+            b')' => return Some(token(instruction, index, start, at + 1, &operands[..count])),
+            _ => return reject(Reason::Unexpected(byte)),
+        }
+        self.state = State::Operands { index, start, operands, count };
+        None
+    }
+}
+
+// The instruction if its operands are acceptable
+fn token(instruction: &Instruction, index: usize, start: u64, end: u64, operands: &[Operand]) -> Result<Token, Rejected> {
+    let reject = |reason| Rejected { instruction: index, start, reason };
+    if !instruction.arity.contains(&operands.len()) {
+        return Err(reject(Reason::Arity(operands.len())));
+    }
+    let mut values = [0; MAX_OPERANDS];
+    for (i, (value, operand)) in values.iter_mut().zip(operands).enumerate() {
+        *value = operand.check(&instruction.operands).map_err(|error| reject(Reason::Operand(i + 1, error)))?;
+    }
+    Ok(Token { instruction: index, operands: values, count: operands.len(), start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::{Conditionals, Semantics};
+    use crate::rng::XorShift;
+
+    // The original part 1 scanner, kept as the reference
+    fn find_multiplication(line: &str) -> Vec<(i32, i32)> {
+        let mut results = Vec::new();
+        let mut i = 0;
+        let chars: Vec<char> = line.chars().collect();
+
+        while i < chars.len() {
+            // Look for "mul(" pattern
+            if i + 3 < chars.len() &&
+               chars[i] == 'm' &&
+               chars[i + 1] == 'u' &&
+               chars[i + 2] == 'l' &&
+               chars[i + 3] == '(' {
+
+                i += 4; // Move past "mul("
+                let mut num1_str = String::new();
+                let mut num2_str = String::new();
+
+                // Get first number
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    num1_str.push(chars[i]);
+                    i += 1;
+                }
+
+                // Check for comma
+                if i < chars.len() && chars[i] == ',' {
+                    i += 1;
+
+                    // Get second number
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        num2_str.push(chars[i]);
+                        i += 1;
+                    }
+
+                    // Check for closing parenthesis
+                    if i < chars.len() && chars[i] == ')' {
+                        // Parse numbers and validate
+                        if let (Ok(x), Ok(y)) = (num1_str.parse::<i32>(), num2_str.parse::<i32>()) {
+                            if (1..=999).contains(&x) && (1..=999).contains(&y) {
+                                results.push((x, y));
+                            }
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+        results
+    }
+
+    // The enabled value instructions of a whole text
+    fn enabled_values(text: &str, registry: &Registry, line_breaks: LineBreaks) -> Vec<Token> {
+        let mut scanner = Scanner::new(registry, line_breaks);
+        let mut enabled = true;
+        let mut results = Vec::new();
+        for byte in text.bytes() {
+            let token = match scanner.push(byte) {
+                Some(Ok(token)) => token,
+                _ => continue,
+            };
+            match registry[token.instruction].semantics {
+                Semantics::Enable(on) => enabled = on,
+                Semantics::Value(_) if enabled => results.push(token),
+                _ => {}
+            }
+        }
+        results
+    }
+
+    // Operands of the enabled mul instructions, with the original grammar
+    fn products(text: &str, conditionals: Conditionals, line_breaks: LineBreaks) -> Vec<(i32, i32)> {
+        let mut registry = Registry::puzzle(conditionals);
+        registry.set_operands(OperandGrammar::ORIGINAL);
+        enabled_values(text, &registry, line_breaks)
+            .iter()
+            .map(|token| (token.operands()[0], token.operands()[1]))
+            .collect()
+    }
+
+    // Checks a whole operand given as text, sign and all
+    fn check_text(grammar: &OperandGrammar, text: &str) -> Result<i32, OperandError> {
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+        if digits.len() < text.len() && !grammar.sign {
+            return Err(OperandError::Sign);
+        }
+        let value = digits.parse::<i64>().ok().filter(|v| *v <= i32::MAX as i64);
+        grammar.check_digits(digits.len(), digits.starts_with('0'), value)
+            .map(|v| if negative { -v } else { v })
+    }
+
+    // Every instruction found by trying each position in turn, the way the
+    // original scanner worked, for any registry
+    fn reference_tokens(text: &str, registry: &Registry) -> Vec<Token> {
+        let bytes = text.as_bytes();
+        let mut results = Vec::new();
+        let mut i = 0;
+
+        let keywords: Vec<String> = (0..registry.len()).map(|index| format!("{}(", registry[index].name)).collect();
+
+        while i < bytes.len() {
+            let index = match keywords.iter().position(|keyword| bytes[i..].starts_with(keyword.as_bytes())) {
+                Some(index) => index,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let instruction = &registry[index];
+            let start = i as u64;
+            i += keywords[index].len();
+            if !instruction.closed {
+                let end = i as u64;
+                results.push(Token { instruction: index, operands: [0; MAX_OPERANDS], count: 0, start, end });
+                continue;
+            }
+
+            // Up to the closing parenthesis or the first byte that can't be
+            // part of the operands, which is skipped too
+            let mut operands: Vec<String> = Vec::new();
+            while i < bytes.len() {
+                let byte = bytes[i];
+                let most = *instruction.arity.end();
+                let signed = (byte == b'-' || byte == b'+') && operands.last().is_none_or(|o| o.is_empty());
+                if (byte.is_ascii_digit() || signed && instruction.operands.sign) && most > 0 {
+                    if operands.is_empty() {
+                        operands.push(String::new());
+                    }
+                    operands.last_mut().unwrap().push(byte as char);
+                } else if byte == b',' && operands.len().max(1) < most {
+                    if operands.is_empty() {
+                        operands.push(String::new());
+                    }
+                    operands.push(String::new());
+                } else {
+                    if byte == b')' && instruction.arity.contains(&operands.len()) {
+                        let values: Option<Vec<i32>> = operands.iter()
+                            .map(|o| check_text(&instruction.operands, o).ok())
+                            .collect();
+                        if let Some(values) = values {
+                            let mut token = Token {
+                                instruction: index,
+                                operands: [0; MAX_OPERANDS],
+                                count: values.len(),
+                                start,
+                                end: i as u64 + 1,
+                            };
+                            token.operands[..values.len()].copy_from_slice(&values);
+                            results.push(token);
+                        }
+                    }
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+        }
+        results
+    }
+
+    // Pieces random corrupted lines are glued together from, biased towards
+    // almost-instructions
+    const FRAGMENTS: [&str; 39] = [
+        "mul(", "mul", "mu", "m", "(", ")", ",", "[", "]", " ", "do()", "don't()", "do(", "don't(",
+        "0", "1", "7", "42", "999", "1000", "0001", "99999999999", "é", "x", "\n",
+        "add(", "sub(", "select()", "where(", "wh", "when(", "fro", "from(", "d", "'",
+        "-", "+", "05", "00",
+    ];
+
+    fn random_line(rng: &mut XorShift) -> String {
+        let len = rng.below(30);
+        (0..len).map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())]).collect()
+    }
+
+    // Lines from d2.txt and q.txt, some with a conditional broken, and the sum
+    // each way of matching conditionals has to give
+    const CONDITIONAL_CASES: [(&str, i32, i32); 7] = [
+        // (line, exact, prefix)
+        ("xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))", 161, 161),
+        ("xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))", 48, 48),
+        ("xmul(2,4)&mul[3,7]!^don't(x_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))", 161, 48),
+        ("xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo(5)?mul(8,5))", 8, 48),
+        ("what()mul(32,31)select()$mul(953,87)^ {)don't(){mul(270,661)@!)who()/!do()?what()who()$why()mul(583,316)*]", 268131, 268131),
+        ("what()mul(32,31)select()$mul(953,87)^ {)don't({mul(270,661)@!)who()/!do()?what()who()$why()mul(583,316)*]", 446601, 268131),
+        ("what()mul(32,31)select()$mul(953,87)^ {)don't(){mul(270,661)@!)who()/!do(?what()who()$why()mul(583,316)*]", 83903, 268131),
+    ];
+
+    fn random_grammar(rng: &mut XorShift) -> OperandGrammar {
+        let min_digits = 1 + rng.below(2);
+        OperandGrammar {
+            min_digits,
+            max_digits: if rng.below(4) == 0 { None } else { Some(min_digits + rng.below(3)) },
+            leading_zeros: rng.chance(50),
+            zero: rng.chance(50),
+            sign: rng.chance(50),
+            max: [9, 99, 999, 1000, i32::MAX][rng.below(5)],
+        }
+    }
+
+    // Candidates and why the puzzle's or the original grammar rejects them
+    const REJECTION_CASES: [(&str, bool, Reason); 10] = [
+        // (text, original grammar, reason)
+        ("mul(0001,5)", false, Reason::Operand(1, OperandError::TooManyDigits)),
+        ("mul(0,5)", true, Reason::Operand(1, OperandError::Zero)),
+        ("mul(5,1000)", false, Reason::Operand(2, OperandError::TooManyDigits)),
+        ("mul(5,1000)", true, Reason::Operand(2, OperandError::TooLarge)),
+        ("mul(5,99999999999)", true, Reason::Operand(2, OperandError::TooLarge)),
+        ("mul(-5,3)", false, Reason::Operand(1, OperandError::Sign)),
+        ("mul(,5)", false, Reason::Operand(1, OperandError::Empty)),
+        ("mul(32,64]", false, Reason::Unexpected(b']')),
+        ("mul(2)", false, Reason::Arity(1)),
+        ("mul(4,", false, Reason::Truncated),
+    ];
+
+    // Line breaks in random places must not change what is found when they are
+    // skipped
+    fn split_lines(rng: &mut XorShift, text: &str) -> String {
+        let mut split = String::new();
+        for ch in text.chars() {
+            if rng.below(8) == 0 {
+                split.push_str(if rng.below(2) == 0 { "\n" } else { "\r\n" });
+            }
+            split.push(ch);
+        }
+        split
+    }
+
+    // Part 1 with line breaks as corruption has to find exactly what
+    // find_multiplication finds, on the sample input and on random corrupted lines
+    #[test]
+    fn matches_find_multiplication() {
+        let mut rng = XorShift(0x2024_1206);
+        let sample = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))".to_string();
+        for line in std::iter::once(sample).chain((0..100_000).map(|_| random_line(&mut rng))) {
+            let found = products(&line, Conditionals::Ignored, LineBreaks::Corrupt);
+            assert_eq!(found, find_multiplication(&line), "in {:?}", line);
+        }
+    }
+
+    // The extended registries with random operand grammars have to find what
+    // trying every position finds
+    #[test]
+    fn matches_every_position() {
+        let mut rng = XorShift(0x2024_1206);
+        let mut grammars = XorShift(0x2024_1207);
+        let mut registries = [Registry::extended(Conditionals::Exact), Registry::extended(Conditionals::Prefix)];
+        for _ in 0..100_000 {
+            let line = random_line(&mut rng);
+            for registry in registries.iter_mut() {
+                registry.set_operands(random_grammar(&mut grammars));
+                let mut scanner = Scanner::new(registry, LineBreaks::Corrupt);
+                let found: Vec<Token> = line.bytes().filter_map(|byte| scanner.push(byte)?.ok()).collect();
+                assert_eq!(found, reference_tokens(&line, registry), "in {:?}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn skipped_line_breaks_change_nothing() {
+        let mut rng = XorShift(0x2024_1206);
+        let mut breaks = XorShift(0x2024_1207);
+        for _ in 0..100_000 {
+            let joined = random_line(&mut rng).replace('\n', "");
+            let split = split_lines(&mut breaks, &joined);
+            let found = products(&split, Conditionals::Exact, LineBreaks::Skip);
+            let expected = products(&joined, Conditionals::Exact, LineBreaks::Corrupt);
+            assert_eq!(found, expected, "in {:?}", split);
+        }
+    }
+
+    #[test]
+    fn conditional_cases() {
+        for &(line, exact, prefix) in CONDITIONAL_CASES.iter() {
+            for (conditionals, expected) in [(Conditionals::Exact, exact), (Conditionals::Prefix, prefix)] {
+                let sum: i32 = products(line, conditionals, LineBreaks::Corrupt).iter().map(|(x, y)| x * y).sum();
+                assert_eq!(sum, expected, "{:?} conditionals for {:?}", conditionals, line);
+            }
+        }
+    }
+
+    #[test]
+    fn rejection_cases() {
+        for &(text, original, expected) in REJECTION_CASES.iter() {
+            let mut registry = Registry::puzzle(Conditionals::Exact);
+            if original {
+                registry.set_operands(OperandGrammar::ORIGINAL);
+            }
+            let mut scanner = Scanner::new(&registry, LineBreaks::Skip);
+            let mut found: Vec<Reason> = text.bytes().filter_map(|byte| scanner.push(byte)?.err()).map(|r| r.reason).collect();
+            found.extend(scanner.finish().map(|r| r.reason));
+            assert_eq!(found, [expected], "rejections of {:?}", text);
+        }
+
+        // The puzzle's grammar takes the zero the original one rejected
+        let registry = Registry::puzzle(Conditionals::Exact);
+        let found: Vec<Vec<i32>> = enabled_values("mul(0,5)mul(05,5)", &registry, LineBreaks::Skip)
+            .iter()
+            .map(|token| token.operands().to_vec())
+            .collect();
+        assert_eq!(found, [vec![0, 5], vec![5, 5]]);
+    }
+}
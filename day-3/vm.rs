@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+
+use crate::instructions::{Registry, Semantics, MAX_OPERANDS};
+use crate::scanner::Token;
+
+// A small machine the recovered instructions run on. A value instruction
+// loads its operands into the registers, then the execution policy decides
+// what happens to the accumulator; the puzzle's policy adds up the products.
+// Control instructions flip the enabled flag, and value instructions are
+// skipped while it is off.
+
+#[derive(Clone, Debug)]
+pub struct Machine {
+    // Operands of the last value instruction executed
+    pub registers: [i64; MAX_OPERANDS],
+    // How many registers it loaded
+    pub loaded: usize,
+    pub accumulator: i64,
+    pub enabled: bool,
+    // Set once the accumulator no longer holds the exact result
+    pub overflowed: bool,
+    pub executed: usize,
+    // Value instructions skipped while disabled
+    pub skipped: usize,
+}
+
+impl Machine {
+    pub fn new(policy: &dyn Policy) -> Self {
+        Machine {
+            registers: [0; MAX_OPERANDS],
+            loaded: 0,
+            accumulator: policy.initial(),
+            enabled: true, // At the beginning of the program, mul is enabled.
+            overflowed: false,
+            executed: 0,
+            skipped: 0,
+        }
+    }
+
+    pub fn operands(&self) -> &[i64] {
+        &self.registers[..self.loaded]
+    }
+
+    /// Runs one instruction, true when it was a value instruction that ran
+    pub fn execute(&mut self, registry: &Registry, token: &Token, policy: &mut dyn Policy) -> bool {
+        match registry[token.instruction].semantics {
+            Semantics::Value(_) if !self.enabled => {
+                self.skipped += 1;
+                false
+            }
+            Semantics::Value(evaluate) => {
+                self.loaded = token.operands().len();
+                for (register, &operand) in self.registers.iter_mut().zip(token.operands()) {
+                    *register = operand as i64;
+                }
+                self.executed += 1;
+                policy.value(self, evaluate(token.operands()));
+                true
+            }
+            Semantics::Enable(on) => {
+                self.executed += 1;
+                if on != self.enabled {
+                    self.enabled = on;
+                    policy.switched(self);
+                }
+                false
+            }
+            Semantics::Ignore => {
+                self.executed += 1;
+                false
+            }
+        }
+    }
+
+    pub fn add(&mut self, value: i64) {
+        let (result, overflowed) = self.accumulator.overflowing_add(value);
+        self.accumulator = result;
+        self.overflowed |= overflowed;
+    }
+
+    pub fn multiply(&mut self, value: i64) {
+        let (result, overflowed) = self.accumulator.overflowing_mul(value);
+        self.accumulator = result;
+        self.overflowed |= overflowed;
+    }
+
+    pub fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Machine state:")?;
+        for (i, register) in self.registers.iter().enumerate() {
+            let note = if i < self.loaded { "" } else { " (not loaded)" };
+            writeln!(out, "  r{} = {}{}", i, register, note)?;
+        }
+        writeln!(out, "  accumulator = {}{}", self.accumulator, if self.overflowed { " (overflowed)" } else { "" })?;
+        writeln!(out, "  enabled = {}", self.enabled)?;
+        writeln!(out, "  executed = {}", self.executed)?;
+        writeln!(out, "  skipped = {}", self.skipped)
+    }
+}
+
+/// What running the instructions computes
+pub trait Policy {
+    // Accumulator before the first instruction
+    fn initial(&self) -> i64 {
+        0
+    }
+
+    /// An enabled value instruction ran, its operands are in the registers
+    /// and `value` is what its semantics give
+    fn value(&mut self, machine: &mut Machine, value: i64);
+
+    /// The enabled flag changed
+    fn switched(&mut self, _machine: &mut Machine) {}
+
+    fn report(&self, machine: &Machine, out: &mut dyn Write) -> io::Result<()>;
+}
+
+pub fn policy(name: &str) -> Option<Box<dyn Policy>> {
+    match name {
+        "sum-of-products" => Some(Box::new(SumOfProducts)),
+        "product-of-sums" => Some(Box::new(ProductOfSums)),
+        "regions" => Some(Box::new(RegionSubtotals::default())),
+        _ => None,
+    }
+}
+
+fn overflow_note(machine: &Machine) -> &'static str {
+    if machine.overflowed { " (overflowed)" } else { "" }
+}
+
+/// The puzzle's answer
+pub struct SumOfProducts;
+
+impl Policy for SumOfProducts {
+    fn value(&mut self, machine: &mut Machine, value: i64) {
+        machine.add(value);
+    }
+
+    fn report(&self, machine: &Machine, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Total sum of all multiplications: {}{}", machine.accumulator, overflow_note(machine))
+    }
+}
+
+/// Multiplies the sums of the operands of each instruction
+pub struct ProductOfSums;
+
+impl Policy for ProductOfSums {
+    fn initial(&self) -> i64 {
+        1
+    }
+
+    fn value(&mut self, machine: &mut Machine, _value: i64) {
+        let sum = machine.operands().iter().sum();
+        machine.multiply(sum);
+    }
+
+    fn report(&self, machine: &Machine, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Product of all operand sums: {}{}", machine.accumulator, overflow_note(machine))
+    }
+}
+
+struct Region {
+    // Number of the instruction that enabled it, 0 for the start
+    start: usize,
+    instructions: usize,
+    subtotal: i64,
+}
+
+/// Sum of products for each stretch between a do() and the next don't()
+#[derive(Default)]
+pub struct RegionSubtotals {
+    regions: Vec<Region>,
+    // Whether the last region is still enabled
+    open: bool,
+}
+
+impl RegionSubtotals {
+    fn open_region(&mut self, start: usize) {
+        self.regions.push(Region { start, instructions: 0, subtotal: 0 });
+        self.open = true;
+    }
+}
+
+impl Policy for RegionSubtotals {
+    fn value(&mut self, machine: &mut Machine, value: i64) {
+        if !self.open {
+            self.open_region(0);
+        }
+        machine.add(value);
+        if let Some(region) = self.regions.last_mut() {
+            region.instructions += 1;
+            region.subtotal = region.subtotal.wrapping_add(value);
+        }
+    }
+
+    fn switched(&mut self, machine: &mut Machine) {
+        if machine.enabled {
+            self.open_region(machine.executed + machine.skipped);
+        } else {
+            self.open = false;
+        }
+    }
+
+    fn report(&self, machine: &Machine, out: &mut dyn Write) -> io::Result<()> {
+        for (i, region) in self.regions.iter().enumerate() {
+            let start = if region.start == 0 { "the start".to_string() } else { format!("instruction {}", region.start) };
+            writeln!(
+                out,
+                "Region {} from {}: {} instructions, subtotal {}",
+                i + 1, start, region.instructions, region.subtotal
+            )?;
+        }
+        writeln!(out, "Total over {} regions: {}{}", self.regions.len(), machine.accumulator, overflow_note(machine))
+    }
+}